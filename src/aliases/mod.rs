@@ -0,0 +1,90 @@
+use lazy_static::lazy_static;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+lazy_static! {
+    /// Aliases by name, kept in alphabetical order for listing
+    static ref ALIASES: Arc<Mutex<BTreeMap<String, String>>> =
+        Arc::new(Mutex::new(BTreeMap::new()));
+}
+
+pub fn define(name: &str, value: &str) {
+    ALIASES
+        .lock()
+        .unwrap()
+        .insert(name.to_string(), value.to_string());
+}
+
+pub fn get(name: &str) -> Option<String> {
+    ALIASES.lock().unwrap().get(name).cloned()
+}
+
+/// Remove an alias, returning true if it was defined
+pub fn remove(name: &str) -> bool {
+    ALIASES.lock().unwrap().remove(name).is_some()
+}
+
+pub fn clear() {
+    ALIASES.lock().unwrap().clear();
+}
+
+/// Every alias with its value, in alphabetical order
+pub fn all() -> Vec<(String, String)> {
+    ALIASES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+/// Return true if a word can be used as the name of an alias
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.chars().any(|x| {
+            x.is_whitespace()
+                || matches!(
+                    x,
+                    '/' | '$'
+                        | '`'
+                        | '='
+                        | '\''
+                        | '"'
+                        | '\\'
+                        | ';'
+                        | '&'
+                        | '|'
+                        | '<'
+                        | '>'
+                        | '('
+                        | ')'
+                )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_define_remove() {
+        define("rush_test_alias", "echo test");
+        assert_eq!(get("rush_test_alias"), Some(String::from("echo test")));
+        assert!(all().contains(&(String::from("rush_test_alias"), String::from("echo test"))));
+        assert!(remove("rush_test_alias"));
+        assert!(!remove("rush_test_alias"));
+        assert_eq!(get("rush_test_alias"), None);
+    }
+
+    #[test]
+    fn test_is_valid_name() {
+        assert!(is_valid_name("ll"));
+        assert!(is_valid_name(".."));
+        assert!(is_valid_name("git-log"));
+        assert!(!is_valid_name("x/y"));
+        assert!(!is_valid_name("a b"));
+        assert!(!is_valid_name(""));
+    }
+}
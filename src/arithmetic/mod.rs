@@ -0,0 +1,488 @@
+use crate::variables::VARIABLES;
+
+/// How deeply variables whose values are expressions may refer to each other
+const MAX_RECURSION: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+}
+
+/// Operators ordered so that the longest one is matched first
+const OPERATORS: [&str; 39] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "|", "^", "~", "!",
+    "?", ":", "=", ",", "(", ")",
+];
+
+/// Evaluate a shell arithmetic expression with 64-bit integers. Variables are
+/// referenced by name, with or without a `$`, and assignments update them.
+pub fn evaluate(expression: &str) -> Result<i64, String> {
+    evaluate_nested(expression, 0)
+}
+
+fn evaluate_nested(expression: &str, depth: usize) -> Result<i64, String> {
+    if depth > MAX_RECURSION {
+        return Err(format!(
+            "{}: expression recursion level exceeded",
+            expression.trim()
+        ));
+    }
+
+    let mut parser = Parser {
+        tokens: tokenize(expression)?,
+        position: 0,
+        evaluate: true,
+        depth,
+    };
+    if parser.tokens.is_empty() {
+        return Ok(0);
+    }
+
+    let value = parser.comma()?;
+    match parser.tokens.get(parser.position) {
+        None => Ok(value),
+        Some(token) => Err(format!(
+            "syntax error in expression (error token is \"{}\")",
+            token_text(token)
+        )),
+    }
+}
+
+/// Parse an integer constant: decimal, octal with a leading `0`, hexadecimal
+/// with a leading `0x`, or `base#digits` for bases from 2 to 64
+pub fn parse_number(text: &str) -> Result<i64, String> {
+    let invalid = || format!("{}: invalid number", text);
+    let too_great = || format!("{}: value too great for base", text);
+
+    let (base, digits) = match text.find('#') {
+        Some(index) => match text[..index].parse::<u32>() {
+            Ok(base) if (2..=64).contains(&base) => (base, &text[index + 1..]),
+            _ => return Err(format!("{}: invalid arithmetic base", text)),
+        },
+        None if text.starts_with("0x") || text.starts_with("0X") => (16, &text[2..]),
+        None if text.len() > 1 && text.starts_with('0') => (8, &text[1..]),
+        None => (10, text),
+    };
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(invalid()),
+        };
+        if digit >= base {
+            return Err(too_great());
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+
+    Ok(value)
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let chars = expression.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut index = 0;
+
+    while index < chars.len() {
+        let c = chars[index];
+        if c.is_whitespace() {
+            index += 1;
+        } else if c.is_ascii_digit() {
+            let start = index;
+            while index < chars.len()
+                && (chars[index].is_ascii_alphanumeric() || "#@_".contains(chars[index]))
+            {
+                index += 1;
+            }
+            let text = chars[start..index].iter().collect::<String>();
+            tokens.push(Token::Number(parse_number(&text)?));
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '$' {
+            let start = if c == '$' { index + 1 } else { index };
+            index = start;
+            while index < chars.len()
+                && (chars[index].is_ascii_alphanumeric() || chars[index] == '_')
+            {
+                index += 1;
+            }
+            if index == start {
+                return Err(format!(
+                    "syntax error: operand expected (error token is \"{}\")",
+                    chars[start - 1..].iter().collect::<String>()
+                ));
+            }
+            tokens.push(Token::Name(chars[start..index].iter().collect()));
+        } else {
+            let rest = chars[index..].iter().collect::<String>();
+            match OPERATORS.iter().find(|x| rest.starts_with(*x)) {
+                Some(operator) => {
+                    tokens.push(Token::Operator(operator));
+                    index += operator.len();
+                }
+                None => {
+                    return Err(format!(
+                        "syntax error: invalid arithmetic operator (error token is \"{}\")",
+                        rest
+                    ))
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn token_text(token: &Token) -> String {
+    match token {
+        Token::Number(x) => x.to_string(),
+        Token::Name(x) => x.clone(),
+        Token::Operator(x) => x.to_string(),
+    }
+}
+
+/// A recursive descent parser that evaluates the expression while parsing it.
+/// Operands of `&&`, `||` and `?:` that are not selected are parsed with
+/// `evaluate` turned off, so that they have no side effects.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    evaluate: bool,
+    depth: usize,
+}
+
+/// Binary operators from the lowest to the highest precedence, starting after
+/// the logical operators
+const BINARY_LEVELS: [&[&str]; 8] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn peek_operator(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Operator(x)) => Some(x),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, operator: &str) -> Result<(), String> {
+        match self.peek_operator() {
+            Some(x) if x == operator => {
+                self.position += 1;
+                Ok(())
+            }
+            _ => Err(match self.peek() {
+                Some(token) => format!(
+                    "syntax error in expression (error token is \"{}\")",
+                    token_text(token)
+                ),
+                None => format!("syntax error: `{}' expected", operator),
+            }),
+        }
+    }
+
+    /// Run a parsing step without side effects
+    fn skip<T>(&mut self, step: fn(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        let evaluate = self.evaluate;
+        self.evaluate = false;
+        let result = step(self);
+        self.evaluate = evaluate;
+        result
+    }
+
+    fn get(&self, name: &str) -> Result<i64, String> {
+        if !self.evaluate {
+            return Ok(0);
+        }
+
+        let value = VARIABLES.lock().unwrap().get(name).unwrap_or_default();
+        if value.trim().is_empty() {
+            Ok(0)
+        } else {
+            evaluate_nested(&value, self.depth + 1)
+        }
+    }
+
+    fn set(&self, name: &str, value: i64) -> Result<i64, String> {
+        if self.evaluate {
+            VARIABLES.lock().unwrap().set(name, &value.to_string())?;
+        }
+        Ok(value)
+    }
+
+    fn comma(&mut self) -> Result<i64, String> {
+        let mut value = self.assignment()?;
+        while self.peek_operator() == Some(",") {
+            self.position += 1;
+            value = self.assignment()?;
+        }
+        Ok(value)
+    }
+
+    fn assignment(&mut self) -> Result<i64, String> {
+        if let (Some(Token::Name(name)), Some(Token::Operator(operator))) =
+            (self.peek(), self.tokens.get(self.position + 1))
+        {
+            if operator.ends_with('=') && !matches!(*operator, "==" | "!=" | "<=" | ">=") {
+                let (name, operator) = (name.clone(), *operator);
+                self.position += 2;
+                let value = self.assignment()?;
+                let value = match operator.trim_end_matches('=') {
+                    "" => value,
+                    x => self.apply(x, self.get(&name)?, value)?,
+                };
+                return self.set(&name, value);
+            }
+        }
+
+        self.ternary()
+    }
+
+    fn ternary(&mut self) -> Result<i64, String> {
+        let condition = self.logical_or()?;
+        if self.peek_operator() != Some("?") {
+            return Ok(condition);
+        }
+        self.position += 1;
+
+        let (yes, no) = if condition != 0 {
+            let yes = self.comma()?;
+            self.expect(":")?;
+            (yes, self.skip(Self::ternary)?)
+        } else {
+            let yes = self.skip(Self::comma)?;
+            self.expect(":")?;
+            (yes, self.ternary()?)
+        };
+        Ok(if condition != 0 { yes } else { no })
+    }
+
+    fn logical_or(&mut self) -> Result<i64, String> {
+        let mut value = self.logical_and()?;
+        while self.peek_operator() == Some("||") {
+            self.position += 1;
+            let right = if value != 0 {
+                self.skip(Self::logical_and)?
+            } else {
+                self.logical_and()?
+            };
+            value = (value != 0 || right != 0) as i64;
+        }
+        Ok(value)
+    }
+
+    fn logical_and(&mut self) -> Result<i64, String> {
+        let mut value = self.binary(0)?;
+        while self.peek_operator() == Some("&&") {
+            self.position += 1;
+            let right = if value == 0 {
+                self.skip(|x| x.binary(0))?
+            } else {
+                self.binary(0)?
+            };
+            value = (value != 0 && right != 0) as i64;
+        }
+        Ok(value)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<i64, String> {
+        if level == BINARY_LEVELS.len() {
+            return self.power();
+        }
+
+        let mut value = self.binary(level + 1)?;
+        while let Some(operator) = self
+            .peek_operator()
+            .filter(|x| BINARY_LEVELS[level].contains(x))
+        {
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            value = self.apply(operator, value, right)?;
+        }
+        Ok(value)
+    }
+
+    fn power(&mut self) -> Result<i64, String> {
+        let base = self.unary()?;
+        if self.peek_operator() != Some("**") {
+            return Ok(base);
+        }
+        self.position += 1;
+        let exponent = self.power()?;
+        self.apply("**", base, exponent)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        let operator = match self.peek_operator() {
+            Some(x @ ("+" | "-" | "!" | "~" | "++" | "--")) => x,
+            _ => return self.postfix(),
+        };
+        self.position += 1;
+
+        if let ("++" | "--", Some(Token::Name(name))) = (operator, self.peek()) {
+            let name = name.clone();
+            self.position += 1;
+            let value = self.get(&name)?;
+            let value = if operator == "++" {
+                value.wrapping_add(1)
+            } else {
+                value.wrapping_sub(1)
+            };
+            return self.set(&name, value);
+        }
+
+        let value = self.unary()?;
+        Ok(match operator {
+            "+" | "++" => value,
+            "-" => value.wrapping_neg(),
+            "--" => value,
+            "!" => (value == 0) as i64,
+            _ => !value,
+        })
+    }
+
+    fn postfix(&mut self) -> Result<i64, String> {
+        if let Some(Token::Name(name)) = self.peek() {
+            let name = name.clone();
+            self.position += 1;
+            let value = self.get(&name)?;
+            match self.peek_operator() {
+                Some("++") => {
+                    self.position += 1;
+                    self.set(&name, value.wrapping_add(1))?;
+                }
+                Some("--") => {
+                    self.position += 1;
+                    self.set(&name, value.wrapping_sub(1))?;
+                }
+                _ => (),
+            }
+            return Ok(value);
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<i64, String> {
+        match self.peek().cloned() {
+            Some(Token::Number(x)) => {
+                self.position += 1;
+                Ok(x)
+            }
+            Some(Token::Operator("(")) => {
+                self.position += 1;
+                let value = self.comma()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            Some(token) => Err(format!(
+                "syntax error: operand expected (error token is \"{}\")",
+                token_text(&token)
+            )),
+            None => Err("syntax error: operand expected".to_string()),
+        }
+    }
+
+    fn apply(&self, operator: &str, a: i64, b: i64) -> Result<i64, String> {
+        if !self.evaluate {
+            return Ok(0);
+        }
+
+        Ok(match operator {
+            "+" => a.wrapping_add(b),
+            "-" => a.wrapping_sub(b),
+            "*" => a.wrapping_mul(b),
+            "/" | "%" if b == 0 => return Err("division by 0".to_string()),
+            "/" => a.wrapping_div(b),
+            "%" => a.wrapping_rem(b),
+            "**" if b < 0 => return Err("exponent less than 0".to_string()),
+            "**" => a.wrapping_pow(b.min(u32::MAX as i64) as u32),
+            "<<" => a.wrapping_shl(b as u32),
+            ">>" => a.wrapping_shr(b as u32),
+            "&" => a & b,
+            "|" => a | b,
+            "^" => a ^ b,
+            "==" => (a == b) as i64,
+            "!=" => (a != b) as i64,
+            "<" => (a < b) as i64,
+            "<=" => (a <= b) as i64,
+            ">" => (a > b) as i64,
+            ">=" => (a >= b) as i64,
+            x => return Err(format!("{}: unknown operator", x)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number("42").unwrap(), 42);
+        assert_eq!(parse_number("0x1F").unwrap(), 31);
+        assert_eq!(parse_number("017").unwrap(), 15);
+        assert_eq!(parse_number("16#ff").unwrap(), 255);
+        assert_eq!(parse_number("2#101").unwrap(), 5);
+        assert_eq!(parse_number("64#_").unwrap(), 63);
+        assert!(parse_number("8#9").is_err());
+        assert!(parse_number("1#0").is_err());
+    }
+
+    #[test]
+    fn test_evaluate_operators() {
+        assert_eq!(evaluate("1 + 2 * 3").unwrap(), 7);
+        assert_eq!(evaluate("(1 + 2) * 3").unwrap(), 9);
+        assert_eq!(evaluate("2 ** 3 ** 2").unwrap(), 512);
+        assert_eq!(evaluate("-7 / 2").unwrap(), -3);
+        assert_eq!(evaluate("-7 % 2").unwrap(), -1);
+        assert_eq!(evaluate("1 << 4 | 1").unwrap(), 17);
+        assert_eq!(evaluate("~0 ^ 5 & 3").unwrap(), -2);
+        assert_eq!(evaluate("!0 && 3 > 2 || 0").unwrap(), 1);
+        assert_eq!(evaluate("0 ? 1 : 2 ? 3 : 4").unwrap(), 3);
+        assert_eq!(evaluate("1, 2, 3").unwrap(), 3);
+        assert_eq!(evaluate("").unwrap(), 0);
+        assert!(evaluate("1 / 0").is_err());
+        assert!(evaluate("1 +").is_err());
+        assert!(evaluate("(1").is_err());
+    }
+
+    #[test]
+    fn test_evaluate_variables() {
+        assert_eq!(evaluate("RUSH_TEST_ARITH = 5").unwrap(), 5);
+        assert_eq!(
+            evaluate("RUSH_TEST_ARITH += 2, $RUSH_TEST_ARITH").unwrap(),
+            7
+        );
+        assert_eq!(evaluate("RUSH_TEST_ARITH++").unwrap(), 7);
+        assert_eq!(evaluate("++RUSH_TEST_ARITH").unwrap(), 9);
+        assert_eq!(evaluate("RUSH_TEST_ARITH <<= 1").unwrap(), 18);
+        assert_eq!(evaluate("RUSH_TEST_ARITH_UNSET + 1").unwrap(), 1);
+
+        // Operands that are not selected are not evaluated
+        assert_eq!(evaluate("0 && RUSH_TEST_ARITH++").unwrap(), 0);
+        assert_eq!(evaluate("1 ? 0 : (RUSH_TEST_ARITH = 1 / 0)").unwrap(), 0);
+        assert_eq!(evaluate("RUSH_TEST_ARITH").unwrap(), 18);
+    }
+}
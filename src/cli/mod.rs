@@ -0,0 +1,133 @@
+/// Where the shell reads its commands from
+#[derive(Debug, PartialEq)]
+pub enum Source {
+    /// The command string given with `-c`
+    Command(String),
+    /// A script file
+    Script(String),
+    /// The standard input, with `-s` or without operands
+    Stdin,
+}
+
+/// The command line arguments of the shell
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    pub source: Source,
+    /// The name of the shell or script reported as `$0`
+    pub name: String,
+    /// The positional parameters `$1`, `$2`, ...
+    pub parameters: Vec<String>,
+    /// Interactive mode was requested with `-i`
+    pub interactive: bool,
+    /// Started as a login shell, with `-l`, `--login` or a name starting with `-`
+    pub login: bool,
+    /// Skip the startup files, `--norc`
+    pub norc: bool,
+    /// Shell options given as single letters, turned on with `-` and off with `+`
+    pub flags: Vec<(char, bool)>,
+}
+
+pub const USAGE: &str =
+    "usage: rush [-eilsx] [--login] [--norc] [-c command [name [arg ...]] | script [arg ...]]";
+
+impl Args {
+    pub fn parse(args: &[String]) -> Result<Args, String> {
+        let name = args
+            .first()
+            .cloned()
+            .unwrap_or_else(|| String::from("rush"));
+        let mut parsed = Args {
+            source: Source::Stdin,
+            login: name.starts_with('-'),
+            name,
+            parameters: vec![],
+            interactive: false,
+            norc: false,
+            flags: vec![],
+        };
+
+        let (mut command, mut stdin) = (false, false);
+        let mut index = 1;
+        while let Some(arg) = args.get(index) {
+            match arg.as_str() {
+                "--" | "-" => {
+                    index += 1;
+                    break;
+                }
+                "--login" => parsed.login = true,
+                "--norc" => parsed.norc = true,
+                x if x.starts_with("--") => return Err(format!("{}: invalid option", x)),
+                x if x.len() > 1 && (x.starts_with('-') || x.starts_with('+')) => {
+                    let value = x.starts_with('-');
+                    for flag in x[1..].chars() {
+                        match flag {
+                            'c' if value => command = true,
+                            's' if value => stdin = true,
+                            'i' if value => parsed.interactive = true,
+                            'l' if value => parsed.login = true,
+                            x => parsed.flags.push((x, value)),
+                        }
+                    }
+                }
+                _ => break,
+            }
+            index += 1;
+        }
+
+        let mut operands = args[index.min(args.len())..].iter().cloned();
+        if command {
+            let text = operands
+                .next()
+                .ok_or_else(|| String::from("-c: option requires an argument"))?;
+            parsed.source = Source::Command(text);
+            if let Some(name) = operands.next() {
+                parsed.name = name;
+            }
+        } else if !stdin {
+            if let Some(script) = operands.next() {
+                parsed.name = script.clone();
+                parsed.source = Source::Script(script);
+            }
+        }
+        parsed.parameters = operands.collect();
+
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(&args.iter().map(|x| x.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse() {
+        let args = parse(&["rush"]).unwrap();
+        assert_eq!(args.source, Source::Stdin);
+        assert_eq!(args.name, "rush");
+        assert!(!args.login);
+
+        let args = parse(&["rush", "-ex", "+e", "script.sh", "a", "-b"]).unwrap();
+        assert_eq!(args.source, Source::Script(String::from("script.sh")));
+        assert_eq!(args.name, "script.sh");
+        assert_eq!(args.parameters, vec!["a", "-b"]);
+        assert_eq!(args.flags, vec![('e', true), ('x', true), ('e', false)]);
+
+        let args = parse(&["-rush", "-c", "echo $0 $1", "name", "1"]).unwrap();
+        assert_eq!(args.source, Source::Command(String::from("echo $0 $1")));
+        assert_eq!(args.name, "name");
+        assert_eq!(args.parameters, vec!["1"]);
+        assert!(args.login);
+
+        let args = parse(&["rush", "-s", "--norc", "a"]).unwrap();
+        assert_eq!(args.source, Source::Stdin);
+        assert_eq!(args.parameters, vec!["a"]);
+        assert!(args.norc);
+
+        assert!(parse(&["rush", "-c"]).is_err());
+        assert!(parse(&["rush", "--nothing"]).is_err());
+    }
+}
@@ -0,0 +1,619 @@
+pub mod spec;
+
+use crate::{
+    aliases, functions, globals::UTIL_COMMANDS, jobs::JOBS,
+    libc_bindings::user_home_dir_by_user_name, prompt::render, variables::VARIABLES,
+};
+use rustyline::{
+    completion::{Completer, Pair},
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator,
+    Context, Helper,
+};
+use spec::{Action, CompleteOption};
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    env, fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+/// Characters escaped with a backslash in completed words outside of quotes
+const SPECIAL_CHARS: &str = " \t\n\\'\"$`&|;<>()*?[]!{}";
+
+/// Reserved words completed in command position
+const RESERVED_WORDS: [&str; 13] = [
+    "case", "do", "done", "elif", "else", "esac", "fi", "for", "function", "if", "then", "until",
+    "while",
+];
+
+/// Line editor support for the interactive shell: completion of the word
+/// under the cursor with Tab, and prompts with non-printing sequences
+#[derive(Default)]
+pub struct ShellHelper {
+    /// The prompt as measured by the line editor
+    visible_prompt: String,
+    /// The prompt as written to the terminal
+    printed_prompt: String,
+}
+
+impl ShellHelper {
+    /// Use a rendered prompt for the next line
+    pub fn set_prompt(&mut self, prompt: &str) {
+        self.visible_prompt = render::visible(prompt);
+        self.printed_prompt = render::printed(prompt);
+    }
+}
+
+impl Helper for ShellHelper {}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
+        &'s self,
+        prompt: &'p str,
+        _default: bool,
+    ) -> Cow<'b, str> {
+        if prompt == self.visible_prompt {
+            Cow::Borrowed(&self.printed_prompt)
+        } else {
+            Cow::Borrowed(prompt)
+        }
+    }
+}
+
+impl Validator for ShellHelper {}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        Ok(complete(&line[..pos]))
+    }
+}
+
+/// What the shell expects at a position of the command line
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Position {
+    /// The name of a command
+    Command,
+    /// An argument of a command
+    Argument,
+    /// The target of a redirection
+    Redirect,
+}
+
+/// Quoting in effect at the end of a word
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Quote {
+    None,
+    Single,
+    Double,
+}
+
+/// The word being completed, with its quotes and escapes removed
+#[derive(Debug, PartialEq)]
+struct Word {
+    /// Byte offset of the word in the line
+    start: usize,
+    text: String,
+    quote: Quote,
+    position: Position,
+    /// The words of the command being completed, starting with its name and
+    /// ending with this word, when it is an argument
+    words: Vec<String>,
+}
+
+/// Find the word that ends at the end of `line` and what the shell expects
+/// there, following quotes, separators and redirections
+fn current_word(line: &str) -> Word {
+    let mut word = Word {
+        start: 0,
+        text: String::new(),
+        quote: Quote::None,
+        position: Position::Command,
+        words: vec![],
+    };
+    let (mut started, mut escaped) = (false, false);
+    let (mut command_seen, mut redirect) = (false, false);
+    let mut previous = None;
+
+    for (index, c) in line.char_indices() {
+        let last = previous.replace(c);
+        if escaped {
+            word.text.push(c);
+            escaped = false;
+            continue;
+        }
+        match (word.quote, c) {
+            (Quote::Single, '\'') | (Quote::Double, '"') => word.quote = Quote::None,
+            (Quote::Single, c) => word.text.push(c),
+            (Quote::Double, '\\') => escaped = true,
+            (Quote::Double, c) => word.text.push(c),
+            (Quote::None, '\\') => {
+                escaped = true;
+                started = true;
+            }
+            (Quote::None, '\'') => {
+                word.quote = Quote::Single;
+                started = true;
+            }
+            (Quote::None, '"') => {
+                word.quote = Quote::Double;
+                started = true;
+            }
+            (Quote::None, ' ' | '\t' | '\n') => {
+                if started {
+                    if redirect {
+                        redirect = false;
+                    } else if command_seen {
+                        word.words.push(word.text.clone());
+                    } else if !is_assignment(&word.text) && !is_command_prefix(&word.text) {
+                        command_seen = true;
+                        word.words.push(word.text.clone());
+                    }
+                }
+                started = false;
+                word.text.clear();
+                word.start = index + 1;
+            }
+            // `>&` and `<&` take a file descriptor, not a new command
+            (Quote::None, '&') if matches!(last, Some('<' | '>')) => {
+                word.start = index + 1;
+            }
+            (Quote::None, ';' | '&' | '|' | '(' | ')' | '`') => {
+                command_seen = false;
+                word.words.clear();
+                redirect = false;
+                started = false;
+                word.text.clear();
+                word.start = index + 1;
+            }
+            (Quote::None, '<' | '>') => {
+                redirect = true;
+                started = false;
+                word.text.clear();
+                word.start = index + 1;
+            }
+            (Quote::None, c) => {
+                word.text.push(c);
+                started = true;
+            }
+        }
+    }
+
+    word.position = if redirect {
+        Position::Redirect
+    } else if command_seen {
+        word.words.push(word.text.clone());
+        Position::Argument
+    } else {
+        word.words.clear();
+        Position::Command
+    };
+    word
+}
+
+/// Reserved words after which a command name is still expected
+fn is_command_prefix(word: &str) -> bool {
+    matches!(word, "!" | "{" | "time") || RESERVED_WORDS.contains(&word)
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.find('=') {
+        Some(index) => crate::variables::is_valid_name(&word[..index]),
+        None => false,
+    }
+}
+
+/// Complete the word at the end of `line`, returning where the word starts
+/// and the candidates replacing it
+pub fn complete(line: &str) -> (usize, Vec<Pair>) {
+    let word = current_word(line);
+    let text = word.text.as_str();
+    let spec = match (word.position, word.words.first()) {
+        (Position::Argument, Some(command)) => spec::get(command),
+        _ => None,
+    };
+
+    let mut candidates = if word.quote == Quote::Single {
+        pairs(paths(text, false), word.quote)
+    } else if let Some(name) = text.strip_prefix("${") {
+        variables(name, "${", "}", word.quote)
+    } else if let Some(name) = text.strip_prefix('$') {
+        variables(name, "$", "", word.quote)
+    } else if let Some(spec) = &spec {
+        let context = spec::Line {
+            line,
+            words: &word.words,
+        };
+        let mut names = spec.generate(text, Some(&context));
+        if names.is_empty() && spec.has_option(CompleteOption::Default) {
+            names = paths(text, false);
+        } else if names.is_empty() && spec.has_option(CompleteOption::Dirnames) {
+            names = paths(text, true);
+        } else if spec.has_option(CompleteOption::Filenames) {
+            for name in names.iter_mut().filter(|x| !x.ends_with('/')) {
+                if expand_tilde(name).is_dir() {
+                    name.push('/');
+                }
+            }
+        }
+        pairs(names, word.quote)
+    } else if text.starts_with('~') && !text.contains('/') {
+        let names = user_names(&text[1..]).into_iter();
+        pairs(names.map(|x| format!("~{}/", x)), word.quote)
+    } else if text.starts_with('%') && word.position != Position::Redirect {
+        let specs = job_specs(&text[1..]).into_iter();
+        pairs(specs.map(|x| format!("%{}", x)), word.quote)
+    } else if word.position == Position::Command && !text.contains('/') {
+        pairs(command_names(text), word.quote)
+    } else {
+        pairs(paths(text, false), word.quote)
+    };
+
+    // A single complete match closes its quotes and is followed by a space,
+    // as the next word starts
+    let nospace = spec.is_some_and(|x| x.has_option(CompleteOption::Nospace));
+    if let [candidate] = candidates.as_mut_slice() {
+        if !candidate.replacement.ends_with('/') && !nospace {
+            match word.quote {
+                Quote::None => (),
+                Quote::Single => candidate.replacement.push('\''),
+                Quote::Double => candidate.replacement.push('"'),
+            }
+            candidate.replacement.push(' ');
+        }
+    }
+    (word.start, candidates)
+}
+
+/// Quote a completed word so that the shell reads it back unchanged, opening
+/// the quotes the word started with
+fn quote(text: &str, quote: Quote) -> String {
+    match quote {
+        Quote::None => {
+            let mut quoted = String::with_capacity(text.len());
+            for c in text.chars() {
+                if SPECIAL_CHARS.contains(c) {
+                    quoted.push('\\');
+                }
+                quoted.push(c);
+            }
+            quoted
+        }
+        Quote::Single => format!("'{}", text.replace('\'', r"'\''")),
+        Quote::Double => {
+            let mut quoted = String::from("\"");
+            for c in text.chars() {
+                if "\"$`\\".contains(c) {
+                    quoted.push('\\');
+                }
+                quoted.push(c);
+            }
+            quoted
+        }
+    }
+}
+
+/// Turn candidates into the text inserted in the line, shown by the last
+/// component of their path. A leading `~user/` stays unquoted so that it is
+/// still expanded.
+fn pairs(names: impl IntoIterator<Item = String>, quote_kind: Quote) -> Vec<Pair> {
+    names
+        .into_iter()
+        .map(|name| {
+            let replacement = match name.find('/') {
+                Some(index) if name.starts_with('~') => {
+                    let (user, rest) = name.split_at(index + 1);
+                    format!("{}{}", user, quote(rest, quote_kind))
+                }
+                _ => quote(&name, quote_kind),
+            };
+            let display = match name.trim_end_matches('/').rsplit('/').next() {
+                Some(x) if name.ends_with('/') => format!("{}/", x),
+                Some(x) => x.to_string(),
+                None => name.clone(),
+            };
+            Pair {
+                display,
+                replacement,
+            }
+        })
+        .collect()
+}
+
+fn variables(prefix: &str, open: &str, close: &str, quote_kind: Quote) -> Vec<Pair> {
+    let names = VARIABLES.lock().unwrap().names();
+    let quote_open = if quote_kind == Quote::Double {
+        "\""
+    } else {
+        ""
+    };
+    names
+        .into_iter()
+        .filter(|x| x.starts_with(prefix))
+        .map(|name| Pair {
+            replacement: format!("{}{}{}{}", quote_open, open, name, close),
+            display: name,
+        })
+        .collect()
+}
+
+/// The names generated by a completion action
+fn action_names(action: Action, prefix: &str) -> Vec<String> {
+    let filter = |names: Vec<String>| {
+        names
+            .into_iter()
+            .filter(|x| x.starts_with(prefix))
+            .collect::<Vec<_>>()
+    };
+    match action {
+        Action::Alias => filter(aliases::all().into_iter().map(|(x, _)| x).collect()),
+        Action::Builtin => {
+            let mut names = filter(UTIL_COMMANDS.keys().map(|x| x.to_string()).collect());
+            names.sort();
+            names
+        }
+        Action::Command => command_names(prefix),
+        Action::Directory => paths(prefix, true),
+        Action::Export => {
+            let variables = VARIABLES.lock().unwrap();
+            let names = variables.names();
+            filter(
+                names
+                    .into_iter()
+                    .filter(|x| variables.is_exported(x))
+                    .collect(),
+            )
+        }
+        Action::File => paths(prefix, false),
+        Action::Function => filter(functions::names()),
+        Action::Job => job_specs(prefix)
+            .into_iter()
+            .filter(|x| !x.starts_with(|c: char| c.is_ascii_digit()))
+            .collect(),
+        Action::Keyword => filter(RESERVED_WORDS.iter().map(|x| x.to_string()).collect()),
+        Action::User => user_names(prefix),
+        Action::Variable => filter(VARIABLES.lock().unwrap().names()),
+    }
+}
+
+fn user_names(prefix: &str) -> Vec<String> {
+    let passwd = fs::read_to_string("/etc/passwd").unwrap_or_default();
+    let names = passwd
+        .lines()
+        .filter_map(|x| x.split(':').next())
+        .filter(|x| !x.is_empty() && x.starts_with(prefix))
+        .map(String::from)
+        .collect::<BTreeSet<_>>();
+    names.into_iter().collect()
+}
+
+/// Job numbers starting with `prefix`, or the names of the jobs whose command
+/// starts with it
+fn job_specs(prefix: &str) -> Vec<String> {
+    let jobs = JOBS.lock().unwrap();
+    let specs = jobs
+        .all()
+        .iter()
+        .filter_map(|job| {
+            if job.id.to_string().starts_with(prefix) {
+                Some(job.id.to_string())
+            } else if !prefix.is_empty() && job.command.starts_with(prefix) {
+                job.command.split_whitespace().next().map(String::from)
+            } else {
+                None
+            }
+        })
+        .collect::<BTreeSet<_>>();
+    specs.into_iter().collect()
+}
+
+/// Builtins, functions, aliases, reserved words and the executables found in
+/// `PATH`
+fn command_names(prefix: &str) -> Vec<String> {
+    let mut names = UTIL_COMMANDS
+        .keys()
+        .map(|x| x.to_string())
+        .chain(functions::names())
+        .chain(aliases::all().into_iter().map(|(name, _)| name))
+        .chain(RESERVED_WORDS.iter().map(|x| x.to_string()))
+        .filter(|x| x.starts_with(prefix))
+        .collect::<BTreeSet<_>>();
+
+    let path = env::var("PATH").unwrap_or_default();
+    for dir in path.split(':').filter(|x| !x.is_empty()) {
+        let entries = match fs::read_dir(dir) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(prefix) && is_executable(&entry.path()) {
+                names.insert(name);
+            }
+        }
+    }
+
+    names.into_iter().collect()
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|x| x.is_file() && x.permissions().mode() & 0o111 != 0)
+}
+
+/// Paths starting with `text`, or only directories. Directories end with `/`,
+/// and hidden files are only listed when the name being completed starts
+/// with `.`.
+fn paths(text: &str, dirs_only: bool) -> Vec<String> {
+    let (dir, prefix) = match text.rfind('/') {
+        Some(index) => text.split_at(index + 1),
+        None => ("", text),
+    };
+
+    let entries = match fs::read_dir(expand_tilde(dir)) {
+        Ok(x) => x,
+        Err(_) => return vec![],
+    };
+    let mut names = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = fs::metadata(entry.path()).is_ok_and(|x| x.is_dir());
+            match is_dir {
+                true => Some(format!("{}{}/", dir, name)),
+                false if dirs_only => None,
+                false => Some(format!("{}{}", dir, name)),
+            }
+        })
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+/// The directory a completed path is listed from
+fn expand_tilde(dir: &str) -> PathBuf {
+    let rest = match dir.strip_prefix('~') {
+        Some(x) => x,
+        None if dir.is_empty() => return PathBuf::from("."),
+        None => return PathBuf::from(dir),
+    };
+
+    let (user, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let path = path.trim_start_matches('/');
+    let home = if user.is_empty() {
+        env::var("HOME").ok()
+    } else {
+        user_home_dir_by_user_name(user).ok()
+    };
+    match home {
+        Some(home) => Path::new(&home).join(path),
+        None => PathBuf::from(dir),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(line: &str) -> (usize, String, Quote, Position) {
+        let word = current_word(line);
+        (word.start, word.text, word.quote, word.position)
+    }
+
+    #[test]
+    fn test_current_word() {
+        assert_eq!(word(""), (0, String::new(), Quote::None, Position::Command));
+        assert_eq!(word("ec"), (0, "ec".into(), Quote::None, Position::Command));
+        assert_eq!(
+            word("ls sr"),
+            (3, "sr".into(), Quote::None, Position::Argument)
+        );
+        assert_eq!(
+            word("ls a\\ b"),
+            (3, "a b".into(), Quote::None, Position::Argument)
+        );
+        assert_eq!(
+            word("cat 'my fi"),
+            (4, "my fi".into(), Quote::Single, Position::Argument)
+        );
+        assert_eq!(
+            word("true && x=1 gi"),
+            (12, "gi".into(), Quote::None, Position::Command)
+        );
+        assert_eq!(
+            word("echo $(ca"),
+            (7, "ca".into(), Quote::None, Position::Command)
+        );
+        assert_eq!(
+            word("echo a >out"),
+            (8, "out".into(), Quote::None, Position::Redirect)
+        );
+        assert_eq!(
+            word("echo a 2>&1 x"),
+            (12, "x".into(), Quote::None, Position::Argument)
+        );
+        assert_eq!(
+            word("if tr"),
+            (3, "tr".into(), Quote::None, Position::Command)
+        );
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("a b(1)", Quote::None), "a\\ b\\(1\\)");
+        assert_eq!(quote("it's", Quote::Single), "'it'\\''s");
+        assert_eq!(quote("a \"$x\"", Quote::Double), "\"a \\\"\\$x\\\"");
+    }
+
+    #[test]
+    fn test_complete_files() {
+        let dir = env::temp_dir().join(format!("rush_test_complete_{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub dir")).unwrap();
+        fs::write(dir.join("file one"), "").unwrap();
+        fs::write(dir.join(".hidden"), "").unwrap();
+        let base = dir.to_string_lossy();
+
+        let replacements = |line: &str| {
+            complete(line)
+                .1
+                .into_iter()
+                .map(|x| x.replacement)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            replacements(&format!("ls {}/", base)),
+            vec![
+                format!("{}/file\\ one", base),
+                format!("{}/sub\\ dir/", base)
+            ]
+        );
+        assert_eq!(
+            replacements(&format!("ls {}/f", base)),
+            vec![format!("{}/file\\ one ", base)]
+        );
+        assert_eq!(
+            replacements(&format!("ls \"{}/s", base)),
+            vec![format!("\"{}/sub dir/", base)]
+        );
+        assert_eq!(
+            replacements(&format!("ls '{}/fi", base)),
+            vec![format!("'{}/file one' ", base)]
+        );
+        assert_eq!(
+            replacements(&format!("ls {}/.h", base)),
+            vec![format!("{}/.hidden ", base)]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_complete_commands() {
+        let (start, candidates) = complete("echo a; shop");
+        assert_eq!(start, 8);
+        assert!(candidates.iter().any(|x| x.replacement == "shopt "));
+
+        VARIABLES
+            .lock()
+            .unwrap()
+            .set("RUSH_TEST_COMPLETE", "1")
+            .unwrap();
+        let (start, candidates) = complete("echo \"${RUSH_TEST_COMP");
+        assert_eq!(start, 5);
+        assert_eq!(candidates[0].replacement, "\"${RUSH_TEST_COMPLETE}\" ");
+    }
+}
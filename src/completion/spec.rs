@@ -0,0 +1,340 @@
+use crate::{executer, glob, variables::VARIABLES};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+/// A kind of names a completion can be generated from, given with `-A` or
+/// its single letter flag
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Alias,
+    Builtin,
+    Command,
+    Directory,
+    Export,
+    File,
+    Function,
+    Job,
+    Keyword,
+    User,
+    Variable,
+}
+
+/// Every action with its name and single letter flag
+const ACTIONS: [(Action, &str, Option<char>); 11] = [
+    (Action::Alias, "alias", Some('a')),
+    (Action::Builtin, "builtin", Some('b')),
+    (Action::Command, "command", Some('c')),
+    (Action::Directory, "directory", Some('d')),
+    (Action::Export, "export", Some('e')),
+    (Action::File, "file", Some('f')),
+    (Action::Function, "function", None),
+    (Action::Job, "job", Some('j')),
+    (Action::Keyword, "keyword", Some('k')),
+    (Action::User, "user", Some('u')),
+    (Action::Variable, "variable", Some('v')),
+];
+
+/// Options changing how the candidates are inserted, given with `-o`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompleteOption {
+    /// Complete file names when the specification generates nothing
+    Default,
+    /// Complete directory names when the specification generates nothing
+    Dirnames,
+    /// The candidates are file names, directories get a trailing `/`
+    Filenames,
+    /// No space is added after a single candidate
+    Nospace,
+}
+
+const OPTIONS: [(CompleteOption, &str); 4] = [
+    (CompleteOption::Default, "default"),
+    (CompleteOption::Dirnames, "dirnames"),
+    (CompleteOption::Filenames, "filenames"),
+    (CompleteOption::Nospace, "nospace"),
+];
+
+/// How the arguments of a command are completed, defined with `complete` or
+/// in the `completions` section of the config file
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompletionSpec {
+    pub actions: Vec<Action>,
+    /// Candidates given with `-W`
+    pub words: Vec<String>,
+    /// A shell function setting `COMPREPLY`, given with `-F`
+    pub function: Option<String>,
+    pub options: Vec<CompleteOption>,
+    /// Added before every candidate, `-P`
+    pub prefix: String,
+    /// Added after every candidate, `-S`
+    pub suffix: String,
+    /// Candidates matching this pattern are removed, or kept if it starts
+    /// with `!`. `&` stands for the word being completed. Given with `-X`.
+    pub filter: Option<String>,
+}
+
+/// The command line around the word being completed, passed to completion
+/// functions
+pub struct Line<'a> {
+    pub line: &'a str,
+    /// The words of the current command, the last one being completed
+    pub words: &'a [String],
+}
+
+lazy_static! {
+    static ref SPECS: Arc<Mutex<BTreeMap<String, CompletionSpec>>> =
+        Arc::new(Mutex::new(BTreeMap::new()));
+}
+
+pub fn define(name: &str, spec: CompletionSpec) {
+    SPECS.lock().unwrap().insert(name.to_string(), spec);
+}
+
+/// The specification of a command, looked up by its name and then by the
+/// last component of its path
+pub fn get(command: &str) -> Option<CompletionSpec> {
+    let specs = SPECS.lock().unwrap();
+    specs.get(command).cloned().or_else(|| {
+        command
+            .rsplit('/')
+            .next()
+            .and_then(|x| specs.get(x))
+            .cloned()
+    })
+}
+
+/// Remove a specification, returning true if it was defined
+pub fn remove(name: &str) -> bool {
+    SPECS.lock().unwrap().remove(name).is_some()
+}
+
+pub fn clear() {
+    SPECS.lock().unwrap().clear();
+}
+
+/// Every specification with its command name, in alphabetical order
+pub fn all() -> Vec<(String, CompletionSpec)> {
+    SPECS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, spec)| (name.clone(), spec.clone()))
+        .collect()
+}
+
+impl CompletionSpec {
+    /// Parse the options of `complete` and `compgen`. Single letter flags not
+    /// describing the specification are returned if they are in `extra`,
+    /// along with the index of the first operand.
+    pub fn parse(args: &[&str], extra: &str) -> Result<(CompletionSpec, Vec<char>, usize), String> {
+        let mut spec = CompletionSpec::default();
+        let mut flags = vec![];
+        let mut index = 0;
+
+        while let Some(arg) = args.get(index) {
+            let cluster = match arg.strip_prefix('-') {
+                Some("-") => {
+                    index += 1;
+                    break;
+                }
+                Some(x) if !x.is_empty() => x,
+                _ => break,
+            };
+            index += 1;
+
+            for (position, flag) in cluster.char_indices() {
+                if !"AWFoPSX".contains(flag) {
+                    match ACTIONS.iter().find(|(_, _, x)| *x == Some(flag)) {
+                        Some((action, _, _)) => spec.actions.push(*action),
+                        None if extra.contains(flag) => flags.push(flag),
+                        None => return Err(format!("-{}: invalid option", flag)),
+                    }
+                    continue;
+                }
+
+                // The value follows the flag or is the next argument
+                let rest = &cluster[position + 1..];
+                let value = if !rest.is_empty() {
+                    rest.to_string()
+                } else {
+                    let value = args
+                        .get(index)
+                        .ok_or_else(|| format!("-{}: option requires an argument", flag))?;
+                    index += 1;
+                    value.to_string()
+                };
+                match flag {
+                    'A' => match ACTIONS.iter().find(|(_, name, _)| *name == value) {
+                        Some((action, _, _)) => spec.actions.push(*action),
+                        None => return Err(format!("{}: invalid action name", value)),
+                    },
+                    'o' => match OPTIONS.iter().find(|(_, name)| *name == value) {
+                        Some((option, _)) => spec.options.push(*option),
+                        None => return Err(format!("{}: invalid option name", value)),
+                    },
+                    'W' => spec.words = value.split_whitespace().map(String::from).collect(),
+                    'F' => spec.function = Some(value),
+                    'P' => spec.prefix = value,
+                    'S' => spec.suffix = value,
+                    _ => spec.filter = Some(value),
+                }
+                break;
+            }
+        }
+
+        Ok((spec, flags, index))
+    }
+
+    /// The options recreating the specification, as printed by `complete -p`
+    pub fn to_args(&self) -> String {
+        let mut args = vec![];
+        for option in &self.options {
+            if let Some((_, name)) = OPTIONS.iter().find(|(x, _)| x == option) {
+                args.push(format!("-o {}", name));
+            }
+        }
+        for action in &self.actions {
+            match ACTIONS.iter().find(|(x, _, _)| x == action) {
+                Some((_, _, Some(flag))) => args.push(format!("-{}", flag)),
+                Some((_, name, None)) => args.push(format!("-A {}", name)),
+                None => (),
+            }
+        }
+        let quote = crate::variables::quote;
+        if !self.words.is_empty() {
+            args.push(format!("-W {}", quote(&self.words.join(" "))));
+        }
+        if let Some(function) = &self.function {
+            args.push(format!("-F {}", quote(function)));
+        }
+        if !self.prefix.is_empty() {
+            args.push(format!("-P {}", quote(&self.prefix)));
+        }
+        if !self.suffix.is_empty() {
+            args.push(format!("-S {}", quote(&self.suffix)));
+        }
+        if let Some(filter) = &self.filter {
+            args.push(format!("-X {}", quote(filter)));
+        }
+        args.join(" ")
+    }
+
+    pub fn has_option(&self, option: CompleteOption) -> bool {
+        self.options.contains(&option)
+    }
+
+    /// Generate the candidates for `word`, unquoted. The words and the names
+    /// of the actions must start with `word`, the replies of the function are
+    /// taken as they are.
+    pub fn generate(&self, word: &str, line: Option<&Line>) -> Vec<String> {
+        let mut candidates = vec![];
+        for action in &self.actions {
+            candidates.extend(super::action_names(*action, word));
+        }
+        candidates.extend(self.words.iter().filter(|x| x.starts_with(word)).cloned());
+        if let Some(function) = &self.function {
+            candidates.extend(call_function(function, word, line));
+        }
+
+        if let Some(filter) = &self.filter {
+            let (keep_matches, pattern) = match filter.strip_prefix('!') {
+                Some(x) => (true, x),
+                None => (false, filter.as_str()),
+            };
+            let pattern = pattern.replace('&', word);
+            candidates.retain(|x| glob::matches(&pattern, x) == keep_matches);
+        }
+
+        candidates
+            .into_iter()
+            .map(|x| format!("{}{}{}", self.prefix, x, self.suffix))
+            .collect()
+    }
+}
+
+/// Run a completion function with the command name, the word being completed
+/// and the previous word as arguments. `COMP_LINE`, `COMP_POINT`, `COMP_WORDS`
+/// and `COMP_CWORD` describe the command line, and the candidates are read
+/// from `COMPREPLY` split on whitespace.
+fn call_function(name: &str, word: &str, line: Option<&Line>) -> Vec<String> {
+    let empty = [];
+    let words = line.map_or(&empty[..], |x| x.words);
+    let command = words.first().map_or("", String::as_str);
+    let previous = match words.len() {
+        0 | 1 => "",
+        x => words[x - 2].as_str(),
+    };
+
+    {
+        let mut variables = VARIABLES.lock().unwrap();
+        if let Some(line) = line {
+            let _ = variables.set("COMP_LINE", line.line);
+            let _ = variables.set("COMP_POINT", &line.line.len().to_string());
+        }
+        let _ = variables.set("COMP_WORDS", &words.join(" "));
+        let _ = variables.set("COMP_CWORD", &words.len().saturating_sub(1).to_string());
+        let _ = variables.unset("COMPREPLY");
+    }
+
+    if executer::run_function(name, &[command, word, previous]).is_none() {
+        return vec![];
+    }
+
+    let reply = VARIABLES.lock().unwrap().get("COMPREPLY");
+    reply
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let (spec, flags, index) =
+            CompletionSpec::parse(&["-p", "-df", "-W", "a b", "-o", "nospace", "cmd"], "p")
+                .unwrap();
+        assert_eq!(flags, vec!['p']);
+        assert_eq!(index, 6);
+        assert_eq!(spec.actions, vec![Action::Directory, Action::File]);
+        assert_eq!(spec.words, vec!["a", "b"]);
+        assert!(spec.has_option(CompleteOption::Nospace));
+        assert_eq!(spec.to_args(), "-o nospace -d -f -W 'a b'");
+
+        let (spec, _, index) =
+            CompletionSpec::parse(&["-Afunction", "-P/", "--", "-x"], "").unwrap();
+        assert_eq!(spec.actions, vec![Action::Function]);
+        assert_eq!(spec.prefix, "/");
+        assert_eq!(index, 3);
+
+        assert!(CompletionSpec::parse(&["-p"], "").is_err());
+        assert!(CompletionSpec::parse(&["-A", "nothing"], "").is_err());
+        assert!(CompletionSpec::parse(&["-W"], "").is_err());
+    }
+
+    #[test]
+    fn test_generate() {
+        let spec = CompletionSpec {
+            words: vec!["status".into(), "stash".into(), "commit".into()],
+            suffix: String::from(":"),
+            ..CompletionSpec::default()
+        };
+        assert_eq!(spec.generate("st", None), vec!["status:", "stash:"]);
+
+        let spec = CompletionSpec {
+            filter: Some(String::from("*sh")),
+            ..spec
+        };
+        assert_eq!(spec.generate("st", None), vec!["status:"]);
+    }
+}
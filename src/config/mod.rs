@@ -1,22 +1,45 @@
+use crate::completion::spec::CompletionSpec;
 use crate::prompt::Prompt;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::env;
 use std::fs::OpenOptions;
 
-#[derive(Default, Debug, PartialEq, Serialize, Deserialize)]
+/// Sizes are numbers of entries, 0 meaning no limit
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct History {
+    /// Entries kept in memory
     pub size: u32,
+    /// Entries kept in the history file
     pub file_size: u32,
+    /// The history file, `~/` standing for the home directory. No file is
+    /// used if it is empty.
     pub path: String,
     pub time_format: String,
 }
 
+impl Default for History {
+    fn default() -> History {
+        History {
+            size: 1000,
+            file_size: 2000,
+            path: String::from("~/.rush_history"),
+            time_format: String::from("%F %T"),
+        }
+    }
+}
+
 #[derive(Default, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Config {
     pub history: History,
     pub prompt: Prompt,
     pub env: HashMap<String, String>,
+    /// Aliases defined at startup, before the rc file runs
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+    /// Completion specifications by command name, like the ones of `complete`
+    #[serde(default)]
+    pub completions: HashMap<String, CompletionSpec>,
 }
 
 impl Config {
@@ -0,0 +1,213 @@
+use super::{capture_output, unparse, word, ExecuteError};
+use crate::{
+    arithmetic, glob,
+    globals::{
+        LAST_BACKGROUND_PID, LAST_STATUS, PIPE_STATUS, POSITIONAL_PARAMETERS, SHELL_NAME, SHELL_PID,
+    },
+    options::OPTIONS,
+    variables::VARIABLES,
+};
+use conch_parser::ast::{DefaultParameterSubstitution, Parameter, ParameterSubstitution};
+use std::sync::atomic::Ordering;
+
+/// Look up the value of a parameter. `$@` and `$*` are joined with spaces.
+pub fn parameter(parameter: &Parameter<String>) -> Option<String> {
+    match parameter {
+        Parameter::At | Parameter::Star => Some(POSITIONAL_PARAMETERS.lock().unwrap().join(" ")),
+        Parameter::Pound => Some(POSITIONAL_PARAMETERS.lock().unwrap().len().to_string()),
+        Parameter::Question => Some(LAST_STATUS.load(Ordering::SeqCst).to_string()),
+        Parameter::Dash => Some(OPTIONS.lock().unwrap().flags()),
+        Parameter::Dollar => Some(SHELL_PID.load(Ordering::SeqCst).to_string()),
+        Parameter::Bang => match LAST_BACKGROUND_PID.load(Ordering::SeqCst) {
+            0 => None,
+            pid => Some(pid.to_string()),
+        },
+        Parameter::Positional(0) => Some(SHELL_NAME.lock().unwrap().clone()),
+        Parameter::Positional(index) => POSITIONAL_PARAMETERS
+            .lock()
+            .unwrap()
+            .get(*index as usize - 1)
+            .cloned(),
+        Parameter::Var(name) => variable(name),
+    }
+}
+
+/// Look up a variable, including the ones computed by the shell on demand
+pub fn variable(name: &str) -> Option<String> {
+    match name {
+        "PIPESTATUS" => Some(
+            PIPE_STATUS
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        _ => VARIABLES.lock().unwrap().get(name),
+    }
+}
+
+/// The separate fields `$@` and `$*` expand to when unquoted
+pub fn positional_fields(parameter: &Parameter<String>) -> Option<Vec<String>> {
+    match parameter {
+        Parameter::At | Parameter::Star => Some(POSITIONAL_PARAMETERS.lock().unwrap().clone()),
+        _ => None,
+    }
+}
+
+fn parameter_name(parameter: &Parameter<String>) -> String {
+    match parameter {
+        Parameter::Var(name) => name.to_string(),
+        Parameter::Positional(index) => index.to_string(),
+        x => x.to_string().trim_start_matches('$').to_string(),
+    }
+}
+
+/// Expand one of the `${...}` forms
+pub fn substitution(substitution: &DefaultParameterSubstitution) -> Result<String, ExecuteError> {
+    use ParameterSubstitution::*;
+
+    // A parameter counts as missing when unset, or also when empty if the
+    // operator was written with a colon, e.g. `${x:-default}`
+    let lookup = |colon: bool, p: &Parameter<String>| match parameter(p) {
+        Some(x) if colon && x.is_empty() => None,
+        x => x,
+    };
+    let word = |word: &Option<_>| match word {
+        Some(x) => word::string(x),
+        None => Ok(String::new()),
+    };
+    let pattern = |word: &Option<_>| match word {
+        Some(x) => word::pattern(x),
+        None => Ok(String::new()),
+    };
+
+    match substitution {
+        Len(p) => Ok(match p {
+            Parameter::At | Parameter::Star => POSITIONAL_PARAMETERS.lock().unwrap().len(),
+            p => value(p)?.chars().count(),
+        }
+        .to_string()),
+        Default(colon, p, default) => match lookup(*colon, p) {
+            Some(x) => Ok(x),
+            None => word(default),
+        },
+        Assign(colon, p, value) => match lookup(*colon, p) {
+            Some(x) => Ok(x),
+            None => {
+                let value = word(value)?;
+                match p {
+                    Parameter::Var(name) => VARIABLES
+                        .lock()
+                        .unwrap()
+                        .set(name, &value)
+                        .map_err(ExecuteError::ParameterError)?,
+                    p => {
+                        return Err(ExecuteError::ParameterError(format!(
+                            "${}: cannot assign in this way",
+                            parameter_name(p)
+                        )))
+                    }
+                }
+                Ok(value)
+            }
+        },
+        Error(colon, p, message) => match lookup(*colon, p) {
+            Some(x) => Ok(x),
+            None => {
+                let message = match word(message)? {
+                    x if x.is_empty() => "parameter null or not set".to_string(),
+                    x => x,
+                };
+                Err(ExecuteError::NullParameter(format!(
+                    "{}: {}",
+                    parameter_name(p),
+                    message
+                )))
+            }
+        },
+        Alternative(colon, p, alternative) => match lookup(*colon, p) {
+            Some(_) => word(alternative),
+            None => Ok(String::new()),
+        },
+        RemoveSmallestSuffix(p, x) => Ok(remove_suffix(&value(p)?, &pattern(x)?, false)),
+        RemoveLargestSuffix(p, x) => Ok(remove_suffix(&value(p)?, &pattern(x)?, true)),
+        RemoveSmallestPrefix(p, x) => Ok(remove_prefix(&value(p)?, &pattern(x)?, false)),
+        RemoveLargestPrefix(p, x) => Ok(remove_prefix(&value(p)?, &pattern(x)?, true)),
+        Command(commands) => capture_output(commands),
+        Arith(expression) => {
+            let expression = expression.as_ref().map(unparse::arithmetic);
+            arithmetic::evaluate(&expression.unwrap_or_default())
+                .map(|x| x.to_string())
+                .map_err(ExecuteError::ArithmeticError)
+        }
+    }
+}
+
+/// The value of a parameter, empty when unset unless `nounset` makes that an
+/// error
+pub fn value(p: &Parameter<String>) -> Result<String, ExecuteError> {
+    match parameter(p) {
+        Some(x) => Ok(x),
+        None if OPTIONS.lock().unwrap().nounset => {
+            Err(ExecuteError::UnboundVariable(parameter_name(p)))
+        }
+        None => Ok(String::new()),
+    }
+}
+
+/// Character boundaries of a string, including its start and end
+fn boundaries(value: &str) -> Vec<usize> {
+    value
+        .char_indices()
+        .map(|(x, _)| x)
+        .chain(std::iter::once(value.len()))
+        .collect()
+}
+
+fn remove_prefix(value: &str, pattern: &str, largest: bool) -> String {
+    let mut boundaries = boundaries(value);
+    if largest {
+        boundaries.reverse();
+    }
+
+    boundaries
+        .into_iter()
+        .find(|x| glob::matches(pattern, &value[..*x]))
+        .map(|x| value[x..].to_string())
+        .unwrap_or_else(|| value.to_string())
+}
+
+fn remove_suffix(value: &str, pattern: &str, largest: bool) -> String {
+    let mut boundaries = boundaries(value);
+    if !largest {
+        boundaries.reverse();
+    }
+
+    boundaries
+        .into_iter()
+        .find(|x| glob::matches(pattern, &value[*x..]))
+        .map(|x| value[..x].to_string())
+        .unwrap_or_else(|| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_prefix() {
+        assert_eq!(remove_prefix("a/b/c", "*/", false), "b/c");
+        assert_eq!(remove_prefix("a/b/c", "*/", true), "c");
+        assert_eq!(remove_prefix("abc", "x", true), "abc");
+        assert_eq!(remove_prefix("äbc", "?", false), "bc");
+    }
+
+    #[test]
+    fn test_remove_suffix() {
+        assert_eq!(remove_suffix("file.tar.gz", ".*", false), "file.tar");
+        assert_eq!(remove_suffix("file.tar.gz", ".*", true), "file");
+        assert_eq!(remove_suffix("abc", "x", false), "abc");
+    }
+}
@@ -1,6 +1,45 @@
-use crate::globals::{self, CURRENT_CHILD, UTIL_COMMANDS};
+mod expand;
+mod redirect;
+pub mod unparse;
+mod word;
+
+use crate::{
+    functions, glob,
+    globals::{
+        self, Flow, FLOW, LAST_BACKGROUND_PID, LAST_STATUS, LOOP_DEPTH, PIPE_STATUS,
+        POSITIONAL_PARAMETERS, SOURCE_DEPTH, UTIL_COMMANDS,
+    },
+    jobs::{self, JOBS},
+    options::OPTIONS,
+    prompt::{self, LineReader, Prompt, PromptResult, ScriptReader},
+    signals::{self, Trap},
+    util::exit_status,
+    variables::{quote, Saved, VARIABLES},
+};
 use conch_parser::ast::*;
-use std::{fs::File, os::unix::process::ExitStatusExt, process::ExitStatus, rc::Rc};
+pub use redirect::above_user_fds;
+use redirect::Redirects;
+use std::{
+    env, fmt,
+    fs::{self, File},
+    io::{self, BufReader},
+    os::unix::{io::RawFd, process::ExitStatusExt},
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    rc::Rc,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+/// Prefix of the lines printed by `set -x` when `PS4` is unset
+const DEFAULT_PS4: &str = "+ ";
+
+/// Number of conditions enclosing the command currently being executed, such
+/// as an `if` guard or the left side of `&&`
+static CONDITION_DEPTH: AtomicUsize = AtomicUsize::new(0);
+/// Number of function calls enclosing the command currently being executed
+static FUNCTION_DEPTH: AtomicUsize = AtomicUsize::new(0);
+/// A trap is running, so no other trap is raised
+static RUNNING_TRAP: AtomicBool = AtomicBool::new(false);
 
 type PipeCommand = PipeableCommand<
     String,
@@ -60,12 +99,39 @@ type SingleCommand = PipeableCommand<
 #[derive(Debug)]
 pub enum ExecuteError {
     StaticError(&'static str),
-    Unsupported(&'static str),
     IoError(std::io::Error),
+    RedirectError(String, std::io::Error),
+    BadFileDescriptor(RawFd),
+    AmbiguousRedirect(String),
+    ParameterError(String),
+    UnboundVariable(String),
+    /// The message of `${parameter:?message}`
+    NullParameter(String),
+    ArithmeticError(String),
+    NoMatch(String),
     NotAnInner,
     Empty,
 }
 
+impl fmt::Display for ExecuteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecuteError::StaticError(x) => write!(f, "{}", x),
+            ExecuteError::IoError(x) => write!(f, "{}", error_text(x)),
+            ExecuteError::RedirectError(path, x) => write!(f, "{}: {}", path, error_text(x)),
+            ExecuteError::BadFileDescriptor(x) => write!(f, "{}: Bad file descriptor", x),
+            ExecuteError::AmbiguousRedirect(x) => write!(f, "{}: ambiguous redirect", x),
+            ExecuteError::ParameterError(x) => write!(f, "{}", x),
+            ExecuteError::UnboundVariable(x) => write!(f, "{}: unbound variable", x),
+            ExecuteError::NullParameter(x) => write!(f, "{}", x),
+            ExecuteError::ArithmeticError(x) => write!(f, "{}", x),
+            ExecuteError::NoMatch(x) => write!(f, "no match: {}", x),
+            ExecuteError::NotAnInner => write!(f, "Not a builtin command"),
+            ExecuteError::Empty => write!(f, "Empty command"),
+        }
+    }
+}
+
 struct Executable<'a> {
     command: &'a str,
     args: &'a [&'a str],
@@ -82,343 +148,1019 @@ impl<'a> Executable<'a> {
     }
 }
 
-#[derive(Debug, Default)]
-struct Redirects {
-    read: Vec<(u16, String)>,
-    write: Vec<(u16, String)>,
-    read_write: Vec<(u16, String)>,
-    append: Vec<(u16, String)>,
-    clobber: Vec<(u16, String)>,
-    dup_read: bool,
-    dup_write: bool,
-}
-
-impl Redirects {
-    fn parse(&mut self, redirect: &Redirect<TopLevelWord<String>>) {
-        match redirect {
-            Redirect::Read(from, to) => match parse_toplevel_word(to) {
-                Ok(path) => self.write.push((from.unwrap_or(globals::STDIN), path)),
-                Err(err) => eprintln!("Error parsing redirect: {:?}", err),
-            },
-            Redirect::Write(from, to) => match parse_toplevel_word(to) {
-                Ok(path) => self.write.push((from.unwrap_or(globals::STDOUT), path)),
-                Err(err) => eprintln!("Error parsing redirect: {:?}", err),
-            },
-            Redirect::ReadWrite(from, to) => match parse_toplevel_word(to) {
-                Ok(path) => self.write.push((from.unwrap_or(globals::STDIN), path)),
-                Err(err) => eprintln!("Error parsing redirect: {:?}", err),
-            },
-            Redirect::Append(from, to) => match parse_toplevel_word(to) {
-                Ok(path) => self.write.push((from.unwrap_or(globals::STDOUT), path)),
-                Err(err) => eprintln!("Error parsing redirect: {:?}", err),
-            },
-            Redirect::Clobber(_, _toplevel_word) => {
-                eprintln!("Unsupported: Clobber");
-            }
-            Redirect::Heredoc(_, _toplevel_word) => {
-                eprintln!("Unsupported: Heredoc");
-            }
-            Redirect::DupRead(from, to) => {
-                eprintln!("{:?}, {:?}", from, to);
+/// Read and execute commands until the input ends, or until `return` is used
+/// at the top level of a sourced file. A syntax error ends a non-interactive
+/// input with the status 2. Returns the status of the last command.
+pub fn execute_input(
+    prompt: &Prompt,
+    reader: &mut dyn LineReader,
+    interactive: bool,
+) -> ExitStatus {
+    let mut status = exit_status(0);
+    loop {
+        run_pending_traps();
+        jobs::notify();
+        match prompt.next(reader) {
+            PromptResult::Commands(x) => {
+                // With noexec a script is only checked for syntax errors
+                if !interactive && OPTIONS.lock().unwrap().noexec {
+                    continue;
+                }
+                signals::clear_interrupted();
+                match execute(&x) {
+                    Ok(x) => status = x,
+                    Err(ExecuteError::Empty) => (),
+                    Err(x) => eprintln!("rush: {}", x),
+                }
+                if FLOW.lock().unwrap().is_some() {
+                    return status;
+                }
             }
-            Redirect::DupWrite(from, to) => {
-                eprintln!("{:?}, {:?}", from, to);
+            PromptResult::Error(x) => {
+                eprintln!("{}", x);
+                if !interactive {
+                    LAST_STATUS.store(2, Ordering::SeqCst);
+                    return exit_status(2);
+                }
             }
+            PromptResult::Eof => return status,
+            PromptResult::Interrupt => (),
         }
     }
 }
 
-fn parse_toplevel_word(word: &TopLevelWord<String>) -> Result<String, ExecuteError> {
-    match word {
-        TopLevelWord(word) => match word {
-            ComplexWord::Single(word) => match word {
-                Word::Simple(word) => match word {
-                    SimpleWord::Literal(x) => Ok(x.to_string()),
-                    SimpleWord::Escaped(x) => Ok(x.to_string()),
-                    SimpleWord::Colon => Err(ExecuteError::Unsupported(":")),
-                    SimpleWord::Param(_x) => Err(ExecuteError::Unsupported("Params")),
-                    SimpleWord::Question => Err(ExecuteError::Unsupported("?")),
-                    SimpleWord::SquareClose => Err(ExecuteError::Unsupported("[")),
-                    SimpleWord::SquareOpen => Err(ExecuteError::Unsupported("]")),
-                    SimpleWord::Star => Err(ExecuteError::Unsupported("*")),
-                    SimpleWord::Subst(_x) => Err(ExecuteError::Unsupported("substring")),
-                    SimpleWord::Tilde => Err(ExecuteError::Unsupported("~")),
-                },
-                Word::SingleQuoted(word) => Ok(word.to_string()),
-                Word::DoubleQuoted(_word) => Err(ExecuteError::StaticError("DoubleQuoted")),
-            },
-            ComplexWord::Concat(_word) => Err(ExecuteError::StaticError("Concat word")),
-        },
+/// Expand a word to a single string without splitting it or matching files,
+/// as done for prompts
+pub fn expand_word(word: &TopLevelWord<String>) -> Result<String, ExecuteError> {
+    word::string(word)
+}
+
+/// Execute the commands of a file in the current shell, as done by `source`
+/// and for startup files. `return` ends the file early with its status.
+pub fn execute_file(path: &Path) -> io::Result<ExitStatus> {
+    let file = above_user_fds(|| File::open(path))?;
+    if file.metadata()?.is_dir() {
+        return Err(io::Error::from_raw_os_error(libc::EISDIR));
+    }
+
+    let mut reader = ScriptReader::new(Box::new(BufReader::new(file)));
+    SOURCE_DEPTH.fetch_add(1, Ordering::SeqCst);
+    let status = execute_input(&Prompt::default(), &mut reader, false);
+    SOURCE_DEPTH.fetch_sub(1, Ordering::SeqCst);
+
+    let mut flow = FLOW.lock().unwrap();
+    if let Some(Flow::Return(code)) = *flow {
+        *flow = None;
+        return Ok(exit_status(code));
     }
+    Ok(status)
 }
 
-pub fn execute(commands: Vec<TopLevelCommand<String>>) -> Result<ExitStatus, ExecuteError> {
-    commands
-        .into_iter()
-        .map(execute_toplevel_command)
-        .last()
-        .unwrap_or(Err(ExecuteError::Empty))
+pub fn execute(commands: &[TopLevelCommand<String>]) -> Result<ExitStatus, ExecuteError> {
+    if commands.is_empty() {
+        return Err(ExecuteError::Empty);
+    }
+
+    Ok(execute_commands(commands))
+}
+
+/// Execute a sequence of commands, such as the body of a compound command.
+/// Errors are reported and turned into a failure status so the remaining
+/// commands can still run. Stops early when `break` or `continue` is pending,
+/// or when SIGINT interrupted the command line.
+fn execute_commands(commands: &[TopLevelCommand<String>]) -> ExitStatus {
+    let mut status = exit_status(0);
+    for command in commands {
+        status = report(execute_toplevel_command(command));
+        LAST_STATUS.store(status_code(status), Ordering::SeqCst);
+        run_pending_traps();
+        if FLOW.lock().unwrap().is_some() || signals::interrupted() {
+            break;
+        }
+    }
+    status
+}
+
+/// Print an execution error and convert it into a failure status. Expanding
+/// an unset parameter with `nounset` or `${parameter:?}` ends a
+/// non-interactive shell.
+fn report(result: Result<ExitStatus, ExecuteError>) -> ExitStatus {
+    match result {
+        Ok(status) => status,
+        Err(ExecuteError::Empty) => exit_status(0),
+        Err(x @ (ExecuteError::UnboundVariable(_) | ExecuteError::NullParameter(_)))
+            if !OPTIONS.lock().unwrap().interactive =>
+        {
+            eprintln!("rush: {}", x);
+            exit(127)
+        }
+        Err(x) => {
+            eprintln!("rush: {}", x);
+            exit_status(1)
+        }
+    }
 }
 
-fn execute_toplevel_command(command: TopLevelCommand<String>) -> Result<ExitStatus, ExecuteError> {
+fn execute_toplevel_command(command: &TopLevelCommand<String>) -> Result<ExitStatus, ExecuteError> {
     match command {
         TopLevelCommand(Command::List(x)) => execute_list(x),
-        TopLevelCommand(Command::Job(x)) => execute_list(x),
+        TopLevelCommand(Command::Job(x)) => execute_background(x),
     }
 }
 
-fn execute_list(command: ListCommand) -> Result<ExitStatus, ExecuteError> {
+fn execute_list(command: &ListCommand) -> Result<ExitStatus, ExecuteError> {
     let AndOrList { first, rest } = command;
-    let mut status = execute_listable(first);
+    // Every command but the last one is tested by the next operator
+    let mut status = as_condition(!rest.is_empty(), || execute_listable(first));
+    let mut ran_last = rest.is_empty();
 
-    if rest.is_empty() {
-        return status;
-    }
+    for (index, command) in rest.iter().enumerate() {
+        if FLOW.lock().unwrap().is_some() {
+            break;
+        }
 
-    for command in rest {
-        match (command, &status) {
-            (AndOr::And(command), Ok(exit_status)) => {
-                if exit_status.success() {
-                    status = execute_listable(command);
-                } else {
-                    break;
-                }
+        // The next command sees the status of the previous one in `$?`
+        if let Ok(x) = &status {
+            LAST_STATUS.store(status_code(*x), Ordering::SeqCst);
+        }
+        let is_last = index + 1 == rest.len();
+        let success = matches!(&status, Ok(x) if x.success());
+        match command {
+            AndOr::And(command) if success => {
+                status = as_condition(!is_last, || execute_listable(command));
+                ran_last = is_last;
             }
-            (AndOr::Or(command), Ok(exit_status)) => {
-                if !exit_status.success() {
-                    status = execute_listable(command);
-                } else {
-                    break;
+            AndOr::Or(command) if !success => {
+                if let Err(x) = status {
+                    LAST_STATUS.store(status_code(report(Err(x))), Ordering::SeqCst);
                 }
+                status = as_condition(!is_last, || execute_listable(command));
+                ran_last = is_last;
             }
-            (AndOr::Or(command), Err(_)) => {
-                status = execute_listable(command);
-            }
-            _ => break,
+            _ => (),
         };
     }
 
+    let last = match rest.last() {
+        Some(AndOr::And(x)) | Some(AndOr::Or(x)) => x,
+        None => first,
+    };
+    let failed = !matches!(&status, Ok(x) if x.success());
+    if failed && ran_last && raises_err(last) && CONDITION_DEPTH.load(Ordering::SeqCst) == 0 {
+        let status = report(status);
+        LAST_STATUS.store(status_code(status), Ordering::SeqCst);
+        run_trap(Trap::Err);
+        if OPTIONS.lock().unwrap().errexit {
+            exit(status_code(status));
+        }
+        return Ok(status);
+    }
+
     status
 }
 
-fn execute_listable(command: ListableCommand<PipeCommand>) -> Result<ExitStatus, ExecuteError> {
+/// Run commands whose status is tested, if `condition` is set. Their failure
+/// doesn't raise ERR.
+fn as_condition<T>(condition: bool, f: impl FnOnce() -> T) -> T {
+    if !condition {
+        return f();
+    }
+    CONDITION_DEPTH.fetch_add(1, Ordering::SeqCst);
+    let result = f();
+    CONDITION_DEPTH.fetch_sub(1, Ordering::SeqCst);
+    result
+}
+
+/// Whether the failure of a command raises ERR. Compound commands other than
+/// subshells leave it to the commands they contain.
+fn raises_err(command: &ListableCommand<PipeCommand>) -> bool {
+    match command {
+        ListableCommand::Pipe(negate, _) => !negate,
+        ListableCommand::Single(PipeableCommand::Compound(command)) => {
+            matches!(command.kind, CompoundCommandKind::Subshell(_))
+        }
+        ListableCommand::Single(PipeableCommand::FunctionDef(..)) => false,
+        ListableCommand::Single(_) => true,
+    }
+}
+
+/// Run the command of a trap, leaving `$?` unchanged. Traps are not raised
+/// again while one is running, and functions don't inherit DEBUG and ERR.
+pub fn run_trap(trap: Trap) {
+    if RUNNING_TRAP.load(Ordering::SeqCst) {
+        return;
+    }
+    if matches!(trap, Trap::Debug | Trap::Err) && FUNCTION_DEPTH.load(Ordering::SeqCst) > 0 {
+        return;
+    }
+    if let Some(command) = signals::trap(trap) {
+        RUNNING_TRAP.store(true, Ordering::SeqCst);
+        run_trap_command(&command);
+        RUNNING_TRAP.store(false, Ordering::SeqCst);
+    }
+}
+
+fn run_trap_command(command: &str) {
+    if command.is_empty() {
+        return;
+    }
+    let commands = match prompt::parse(command) {
+        Ok(x) => x,
+        Err(x) => {
+            eprintln!("rush: trap: {}", x);
+            return;
+        }
+    };
+
+    let status = LAST_STATUS.load(Ordering::SeqCst);
+    execute_commands(&commands);
+    LAST_STATUS.store(status, Ordering::SeqCst);
+}
+
+/// Run the traps of the signals caught since the last time
+pub fn run_pending_traps() {
+    for trap in signals::take_pending() {
+        run_trap(trap);
+    }
+}
+
+/// Run the EXIT trap and exit the shell
+pub fn exit(code: i32) -> ! {
+    LAST_STATUS.store(code, Ordering::SeqCst);
+    if let Some(command) = signals::trap(Trap::Exit) {
+        // An `exit` in the trap must not run it again
+        signals::set_trap(Trap::Exit, None);
+        run_trap_command(&command);
+    }
+    std::process::exit(code);
+}
+
+fn execute_listable(command: &ListableCommand<PipeCommand>) -> Result<ExitStatus, ExecuteError> {
     match command {
         ListableCommand::Pipe(negate_last, command) => {
-            let status = execute_pipe(command)?;
-            if negate_last {
+            let status = as_condition(*negate_last, || execute_pipe(command))?;
+            if *negate_last {
                 if status.success() {
-                    Ok(ExitStatusExt::from_raw(1))
+                    Ok(exit_status(1))
                 } else {
-                    Ok(ExitStatusExt::from_raw(0))
+                    Ok(exit_status(0))
                 }
             } else {
                 Ok(status)
             }
         }
-        ListableCommand::Single(command) => execute_single(command),
+        ListableCommand::Single(command) => {
+            let status = execute_single(command, false);
+            if let Ok(status) = status {
+                *PIPE_STATUS.lock().unwrap() = vec![status_code(status)];
+            }
+            status
+        }
     }
 }
 
-fn execute_pipe(commands: Vec<PipeCommand>) -> Result<ExitStatus, ExecuteError> {
+fn execute_pipe(commands: &[PipeCommand]) -> Result<ExitStatus, ExecuteError> {
+    use nix::unistd::{
+        close, dup2, pipe, setpgid,
+        ForkResult::{Child, Parent},
+        Pid,
+    };
+
     if commands.is_empty() {
         return Err(ExecuteError::StaticError("Invalid empty pipe command"));
     }
     if commands.len() == 1 {
-        return execute_single(commands.into_iter().next().unwrap());
+        return execute_single(&commands[0], false);
     }
 
-    Err(ExecuteError::Unsupported("Pipe"))
+    let job_control = jobs::enabled();
+    let stages = commands.len();
+    let mut children = Vec::with_capacity(stages);
+    let mut previous_read = None;
+    // Every stage joins the process group of the first one
+    let mut pgid = Pid::from_raw(0);
+
+    for (index, command) in commands.iter().enumerate() {
+        let is_last = index + 1 == stages;
+        let next_pipe = if is_last {
+            None
+        } else {
+            match pipe() {
+                Ok(x) => Some(x),
+                Err(_) => {
+                    close_fd(previous_read);
+                    wait_children(&children);
+                    return Err(ExecuteError::StaticError("Failed to create pipe"));
+                }
+            }
+        };
+
+        match fork_subshell() {
+            Ok(Child) => {
+                if job_control {
+                    jobs::enter_group(pgid, true);
+                }
+                if let Some(read) = previous_read {
+                    let _ = dup2(read, globals::STDIN as i32);
+                    let _ = close(read);
+                }
+                if let Some((read, write)) = next_pipe {
+                    let _ = close(read);
+                    let _ = dup2(write, globals::STDOUT as i32);
+                    let _ = close(write);
+                }
+
+                let code = match execute_single(command, true) {
+                    Ok(status) => status_code(status),
+                    Err(x) => {
+                        eprintln!("{}", x);
+                        1
+                    }
+                };
+                std::process::exit(code);
+            }
+            Ok(Parent { child }) => {
+                if pgid.as_raw() == 0 {
+                    pgid = child;
+                }
+                if job_control {
+                    let _ = setpgid(child, pgid);
+                }
+                children.push(child);
+                close_fd(previous_read);
+                previous_read = next_pipe.map(|(read, write)| {
+                    let _ = close(write);
+                    read
+                });
+            }
+            Err(x) => {
+                close_fd(previous_read);
+                if let Some((read, write)) = next_pipe {
+                    let _ = close(read);
+                    let _ = close(write);
+                }
+                wait_children(&children);
+                return Err(x);
+            }
+        }
+    }
+
+    let statuses = jobs::run_foreground(pgid, &children, &unparse::pipeline(commands));
+    let mut last = *statuses.last().unwrap_or(&1);
+    if OPTIONS.lock().unwrap().pipefail {
+        // The status of the rightmost command that failed
+        last = statuses
+            .iter()
+            .rev()
+            .copied()
+            .find(|x| *x != 0)
+            .unwrap_or(0);
+    }
+    *PIPE_STATUS.lock().unwrap() = statuses;
+    Ok(exit_status(last))
+}
+
+/// Start an and-or list in the background as a new job
+fn execute_background(list: &ListCommand) -> Result<ExitStatus, ExecuteError> {
+    use nix::{
+        fcntl::{open, OFlag},
+        sys::stat::Mode,
+        unistd::{
+            close, dup2, setpgid,
+            ForkResult::{Child, Parent},
+            Pid,
+        },
+    };
+
+    let job_control = jobs::enabled();
+    match fork_subshell()? {
+        Child => {
+            if job_control {
+                jobs::enter_group(Pid::from_raw(0), false);
+            } else if let Ok(fd) = open("/dev/null", OFlag::O_RDONLY, Mode::empty()) {
+                // Without job control the command must not read the terminal
+                let _ = dup2(fd, globals::STDIN as i32);
+                let _ = close(fd);
+            }
+
+            let status = match list {
+                AndOrList {
+                    first: ListableCommand::Single(command),
+                    rest,
+                } if rest.is_empty() => execute_single(command, true),
+                list => execute_list(list),
+            };
+            std::process::exit(status_code(report(status)));
+        }
+        Parent { child } => {
+            if job_control {
+                let _ = setpgid(child, child);
+            }
+            let id = JOBS
+                .lock()
+                .unwrap()
+                .add(child, &[child], &unparse::list(list));
+            LAST_BACKGROUND_PID.store(child.as_raw(), Ordering::SeqCst);
+            if job_control {
+                eprintln!("[{}] {}", id, child);
+            }
+            Ok(exit_status(0))
+        }
+    }
 }
 
-fn execute_single(command: SingleCommand) -> Result<ExitStatus, ExecuteError> {
+/// Fork the shell to run commands in a subshell. The child forgets the loops
+/// of its parent, has no job control and can be interrupted or terminated.
+fn fork_subshell() -> Result<nix::unistd::ForkResult, ExecuteError> {
+    use nix::unistd::{fork, ForkResult};
+    use std::io::Write;
+
+    let _ = std::io::stdout().flush();
+    let _ = std::io::stderr().flush();
+
+    // Reaping runs on another thread. Holding the job table across the fork
+    // keeps the child from inheriting it locked.
+    let jobs = JOBS.lock().unwrap();
+    let forked = unsafe { fork() };
+    drop(jobs);
+
+    match forked {
+        Ok(ForkResult::Child) => {
+            signals::reset_traps();
+            signals::restore_terminating();
+            LOOP_DEPTH.store(0, Ordering::SeqCst);
+            jobs::JOB_CONTROL.store(false, Ordering::SeqCst);
+            Ok(ForkResult::Child)
+        }
+        Ok(parent) => Ok(parent),
+        Err(_) => Err(ExecuteError::StaticError(
+            "Fork Failed: Unable to create child process!",
+        )),
+    }
+}
+
+fn close_fd(fd: Option<std::os::unix::io::RawFd>) {
+    if let Some(fd) = fd {
+        let _ = nix::unistd::close(fd);
+    }
+}
+
+fn wait_children(children: &[nix::unistd::Pid]) {
+    for child in children {
+        let _ = nix::sys::wait::waitpid(*child, None);
+    }
+}
+
+/// Convert an exit status into the numeric value reported to the user, 128
+/// plus the signal number for a process killed by a signal
+pub fn status_code(status: ExitStatus) -> i32 {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        _ => 1,
+    }
+}
+
+/// Execute a single command. With `exec` set the process is about to exit, so
+/// an external command replaces it instead of running in a child process.
+fn execute_single(command: &SingleCommand, exec: bool) -> Result<ExitStatus, ExecuteError> {
+    match command {
+        PipeableCommand::Simple(command) => execute_simple(command, exec),
+        PipeableCommand::Compound(command) => execute_compound_command(command),
+        PipeableCommand::FunctionDef(name, body) => {
+            functions::define(name, body.clone());
+            Ok(exit_status(0))
+        }
+    }
+}
+
+/// Execute a compound command with its redirections applied
+fn execute_compound_command(command: &DefaultCompoundCommand) -> Result<ExitStatus, ExecuteError> {
+    let mut redirects = Redirects::default();
+    for redirect in &command.io {
+        redirects.parse(redirect)?;
+    }
+    let _guard = redirects.apply()?;
+
+    execute_compound(&command.kind)
+}
+
+/// Call a shell function by name, if it is defined
+pub fn run_function(name: &str, args: &[&str]) -> Option<ExitStatus> {
+    functions::get(name).map(|body| report(call_function(&body, args)))
+}
+
+/// Call a shell function with its own positional parameters and local scope
+fn call_function(body: &DefaultCompoundCommand, args: &[&str]) -> Result<ExitStatus, ExecuteError> {
+    let parameters = args.iter().map(|x| x.to_string()).collect();
+    let caller_parameters =
+        std::mem::replace(&mut *POSITIONAL_PARAMETERS.lock().unwrap(), parameters);
+    VARIABLES.lock().unwrap().push_scope();
+    FUNCTION_DEPTH.fetch_add(1, Ordering::SeqCst);
+
+    let status = execute_compound_command(body);
+    run_trap(Trap::Return);
+
+    FUNCTION_DEPTH.fetch_sub(1, Ordering::SeqCst);
+    VARIABLES.lock().unwrap().pop_scope();
+    *POSITIONAL_PARAMETERS.lock().unwrap() = caller_parameters;
+
+    let mut flow = FLOW.lock().unwrap();
+    if let Some(Flow::Return(code)) = *flow {
+        *flow = None;
+        return Ok(exit_status(code));
+    }
+    status
+}
+
+fn execute_compound(command: &DefaultCompoundCommandKind) -> Result<ExitStatus, ExecuteError> {
     match command {
-        PipeableCommand::Simple(command) => execute_simple(command),
-        PipeableCommand::Compound(command) => {
-            if !command.io.is_empty() {
-                return Err(ExecuteError::Unsupported("Compound> command io"));
+        CompoundCommandKind::Subshell(commands) => execute_subshell(commands),
+        CompoundCommandKind::Brace(commands) => Ok(execute_commands(commands)),
+        CompoundCommandKind::While(GuardBodyPair { guard, body }) => {
+            Ok(execute_loop(guard, body, false))
+        }
+        CompoundCommandKind::Until(GuardBodyPair { guard, body }) => {
+            Ok(execute_loop(guard, body, true))
+        }
+        CompoundCommandKind::If {
+            conditionals,
+            else_branch,
+        } => {
+            for GuardBodyPair { guard, body } in conditionals {
+                let guard_status = as_condition(true, || execute_commands(guard));
+                if FLOW.lock().unwrap().is_some() {
+                    return Ok(guard_status);
+                }
+                if guard_status.success() {
+                    return Ok(execute_commands(body));
+                }
             }
 
-            match command.kind {
-                CompoundCommandKind::Subshell(commands) => execute_subshell(commands),
-                CompoundCommandKind::Brace(_commands) => {
-                    Err(ExecuteError::Unsupported("Compound command Brace"))
+            match else_branch {
+                Some(body) => Ok(execute_commands(body)),
+                None => Ok(exit_status(0)),
+            }
+        }
+        CompoundCommandKind::For { var, words, body } => {
+            let values = match words {
+                Some(words) => {
+                    let mut values = vec![];
+                    for word in words {
+                        values.extend(word::fields(word)?);
+                    }
+                    values
                 }
-                CompoundCommandKind::While(_guard_body_pair) => {
-                    Err(ExecuteError::Unsupported("Compound command While"))
+                None => POSITIONAL_PARAMETERS.lock().unwrap().clone(),
+            };
+
+            let mut status = exit_status(0);
+            LOOP_DEPTH.fetch_add(1, Ordering::SeqCst);
+            for value in values {
+                if let Err(x) = VARIABLES.lock().unwrap().set(var, &value) {
+                    LOOP_DEPTH.fetch_sub(1, Ordering::SeqCst);
+                    return Err(ExecuteError::ParameterError(x));
                 }
-                CompoundCommandKind::Until(_guard_body_pair) => {
-                    Err(ExecuteError::Unsupported("Compound command Until"))
+                status = execute_commands(body);
+                if let LoopControl::Break = take_loop_control() {
+                    break;
                 }
-                CompoundCommandKind::If {
-                    conditionals: _,
-                    else_branch: _,
-                } => Err(ExecuteError::Unsupported("Compound command If")),
-                CompoundCommandKind::For {
-                    var: _,
-                    words: _,
-                    body: _,
-                } => Err(ExecuteError::Unsupported("Compound command For")),
-                CompoundCommandKind::Case { word: _, arms: _ } => {
-                    Err(ExecuteError::Unsupported("Compound command Case"))
+            }
+            LOOP_DEPTH.fetch_sub(1, Ordering::SeqCst);
+
+            Ok(status)
+        }
+        CompoundCommandKind::Case { word, arms } => {
+            let value = word::string(word)?;
+            for PatternBodyPair { patterns, body } in arms {
+                for pattern in patterns {
+                    if glob::matches(&word::pattern(pattern)?, &value) {
+                        return Ok(execute_commands(body));
+                    }
                 }
             }
+
+            Ok(exit_status(0))
         }
-        PipeableCommand::FunctionDef(_name, _body) => {
-            Err(ExecuteError::Unsupported("Function definition"))
+    }
+}
+
+/// Run a `while` loop, or an `until` loop if `until` is set
+fn execute_loop(
+    guard: &[TopLevelCommand<String>],
+    body: &[TopLevelCommand<String>],
+    until: bool,
+) -> ExitStatus {
+    let mut status = exit_status(0);
+    LOOP_DEPTH.fetch_add(1, Ordering::SeqCst);
+    loop {
+        let guard_status = as_condition(true, || execute_commands(guard));
+        if let LoopControl::Break = take_loop_control() {
+            break;
+        }
+        if guard_status.success() == until {
+            break;
+        }
+
+        status = execute_commands(body);
+        if let LoopControl::Break = take_loop_control() {
+            break;
         }
     }
+    LOOP_DEPTH.fetch_sub(1, Ordering::SeqCst);
+
+    status
+}
+
+enum LoopControl {
+    Break,
+    Next,
+}
+
+/// Consume a pending `break` or `continue` aimed at the innermost loop. Requests
+/// for outer loops are passed on with one level less, and a pending `return`
+/// or an interruption by SIGINT leaves every loop.
+fn take_loop_control() -> LoopControl {
+    let mut flow = FLOW.lock().unwrap();
+    let control = match *flow {
+        Some(Flow::Break(levels)) => {
+            *flow = if levels > 1 {
+                Some(Flow::Break(levels - 1))
+            } else {
+                None
+            };
+            LoopControl::Break
+        }
+        Some(Flow::Continue(levels)) => {
+            if levels > 1 {
+                *flow = Some(Flow::Continue(levels - 1));
+                LoopControl::Break
+            } else {
+                *flow = None;
+                LoopControl::Next
+            }
+        }
+        Some(Flow::Return(_)) => LoopControl::Break,
+        None => LoopControl::Next,
+    };
+    if signals::interrupted() {
+        LoopControl::Break
+    } else {
+        control
+    }
 }
 
-fn execute_subshell(commands: Vec<TopLevelCommand<String>>) -> Result<ExitStatus, ExecuteError> {
+fn execute_subshell(commands: &[TopLevelCommand<String>]) -> Result<ExitStatus, ExecuteError> {
+    use nix::unistd::{
+        setpgid,
+        ForkResult::{Child, Parent},
+        Pid,
+    };
+
+    let job_control = jobs::enabled();
+    match fork_subshell()? {
+        Child => {
+            if job_control {
+                jobs::enter_group(Pid::from_raw(0), true);
+            }
+            let status = execute_commands(commands);
+            std::process::exit(status_code(status));
+        }
+        Parent { child } => {
+            if job_control {
+                let _ = setpgid(child, child);
+            }
+            let command = commands
+                .iter()
+                .map(unparse::command)
+                .collect::<Vec<_>>()
+                .join("; ");
+            let codes = jobs::run_foreground(child, &[child], &format!("( {} )", command));
+            Ok(exit_status(codes.last().copied().unwrap_or(1)))
+        }
+    }
+}
+
+/// Run commands in a forked child and capture their standard output, without
+/// the trailing newlines, for command substitution
+fn capture_output(commands: &[TopLevelCommand<String>]) -> Result<String, ExecuteError> {
     use nix::{
-        sys::wait::{wait, WaitStatus},
+        sys::wait::{waitpid, WaitStatus},
         unistd::{
-            fork,
+            close, dup2, pipe,
             ForkResult::{Child, Parent},
         },
     };
+    use std::{fs::File, io::Read, os::unix::io::FromRawFd};
 
-    unsafe {
-        let pid = fork();
-        match pid.expect("Fork Failed: Unable to create child process!") {
-            Child => match execute(commands) {
-                Ok(status) => std::process::exit(status.code().unwrap_or(1)),
-                Err(x) => {
-                    eprintln!("Error in subshell: {:?}", x);
-                    std::process::exit(1);
-                }
-            },
-            Parent { child: _ } => match wait() {
-                Ok(WaitStatus::Exited(_, status)) => Ok(ExitStatusExt::from_raw(status)),
-                err => {
-                    eprintln!("Error with subshell execution: {:?}", err);
-                    Err(ExecuteError::StaticError("Failed to execute subshell"))
-                }
-            },
+    let (read, write) = pipe().map_err(|_| ExecuteError::StaticError("Failed to create pipe"))?;
+
+    match fork_subshell() {
+        Ok(Child) => {
+            let _ = close(read);
+            let _ = dup2(write, globals::STDOUT as i32);
+            let _ = close(write);
+
+            let status = execute_commands(commands);
+            std::process::exit(status_code(status));
+        }
+        Ok(Parent { child }) => {
+            let _ = close(write);
+            let mut output = vec![];
+            let read_result = unsafe { File::from_raw_fd(read) }.read_to_end(&mut output);
+
+            let code = match waitpid(child, None) {
+                Ok(WaitStatus::Exited(_, code)) => code,
+                Ok(WaitStatus::Signaled(_, signal, _)) => 128 + signal as i32,
+                _ => 1,
+            };
+            LAST_STATUS.store(code, Ordering::SeqCst);
+            read_result.map_err(ExecuteError::IoError)?;
+
+            let mut output = String::from_utf8_lossy(&output).into_owned();
+            output.truncate(output.trim_end_matches('\n').len());
+            Ok(output)
+        }
+        Err(_) => {
+            let _ = close(read);
+            let _ = close(write);
+            Err(ExecuteError::StaticError(
+                "Fork Failed: Unable to create child process!",
+            ))
         }
     }
 }
 
-fn execute_simple(
-    command: Box<SimpleCommand<String, TopLevelWord<String>, Redirect<TopLevelWord<String>>>>,
-) -> Result<ExitStatus, ExecuteError> {
+fn execute_simple(command: &DefaultSimpleCommand, exec: bool) -> Result<ExitStatus, ExecuteError> {
     let SimpleCommand {
         redirects_or_env_vars,
         redirects_or_cmd_words,
-    } = command.as_ref();
-    if !redirects_or_env_vars.is_empty() {
-        return Err(ExecuteError::StaticError(
-            "Unsupported: environment variables or redirects",
-        ));
-    }
-
+    } = command;
     let mut args = vec![];
+    let mut assignments = vec![];
     let mut redirects = Redirects::default();
+    // Whether a command word has a command substitution, which sets the
+    // status if the words expand to nothing
+    let mut substituted = false;
+    run_trap(Trap::Debug);
+
+    for prefix in redirects_or_env_vars {
+        match prefix {
+            RedirectOrEnvVar::Redirect(redirect) => redirects.parse(redirect)?,
+            RedirectOrEnvVar::EnvVar(name, value) => assignments.push((name, value)),
+        }
+    }
 
     for word in redirects_or_cmd_words {
         match word {
-            RedirectOrCmdWord::CmdWord(TopLevelWord(word)) => match word {
-                ComplexWord::Single(word) => match word {
-                    Word::Simple(word) => match word {
-                        SimpleWord::Literal(x) => args.push(x.as_ref()),
-                        SimpleWord::Escaped(x) => args.push(x.as_ref()),
-                        SimpleWord::Colon => {
-                            return Err(ExecuteError::Unsupported(":"));
-                        }
-                        SimpleWord::Param(_x) => {
-                            return Err(ExecuteError::Unsupported("Params"));
-                        }
-                        SimpleWord::Question => {
-                            return Err(ExecuteError::Unsupported("?"));
-                        }
-                        SimpleWord::SquareClose => {
-                            return Err(ExecuteError::Unsupported("["));
-                        }
-                        SimpleWord::SquareOpen => {
-                            return Err(ExecuteError::Unsupported("]"));
-                        }
-                        SimpleWord::Star => {
-                            return Err(ExecuteError::Unsupported("*"));
-                        }
-                        SimpleWord::Subst(_x) => {
-                            return Err(ExecuteError::Unsupported("substring"));
-                        }
-                        SimpleWord::Tilde => {
-                            return Err(ExecuteError::Unsupported("~"));
-                        }
-                    },
-                    Word::SingleQuoted(word) => args.push(word.as_ref()),
-                    Word::DoubleQuoted(_word) => {
-                        return Err(ExecuteError::Unsupported("DoubleQuoted"));
-                    }
-                },
-                ComplexWord::Concat(_word) => {
-                    return Err(ExecuteError::Unsupported("Concat word"));
-                }
-            },
-            RedirectOrCmdWord::Redirect(redirect) => {
-                redirects.parse(redirect);
+            // Arguments of declaration builtins that look like assignments
+            // are expanded like assignments, without field splitting
+            RedirectOrCmdWord::CmdWord(word)
+                if is_declaration(args.first()) && word::is_assignment(word) =>
+            {
+                args.push(word::assignment(word)?)
+            }
+            RedirectOrCmdWord::CmdWord(word) => {
+                substituted |= word::has_substitution(word);
+                args.extend(word::fields(word)?)
             }
+            RedirectOrCmdWord::Redirect(redirect) => redirects.parse(redirect)?,
         }
     }
 
+    // Assignments are expanded after the command words, so `X=1 echo $X`
+    // still prints the old value
+    let mut values = vec![];
+    for (name, value) in &assignments {
+        let value = match value {
+            Some(word) => word::assignment(word)?,
+            None => String::new(),
+        };
+        values.push((name.as_str(), value));
+    }
+    if OPTIONS.lock().unwrap().xtrace {
+        trace(&values, &args);
+    }
+
+    if args.is_empty() {
+        // Without a command the status is the one of the last command
+        // substitution, if there was any
+        let substituted = substituted
+            || assignments
+                .iter()
+                .any(|(_, value)| value.as_ref().is_some_and(word::has_substitution));
+        let status = if substituted {
+            LAST_STATUS.load(Ordering::SeqCst)
+        } else {
+            0
+        };
+
+        assign(&values)?;
+        return redirects.apply().map(|_| exit_status(status));
+    }
+
+    // Assignments before special builtins stay in effect, while the others
+    // are only exported to the command
+    let _assignments = if SPECIAL_BUILTINS.contains(&args[0].as_str()) {
+        assign(&values)?;
+        None
+    } else {
+        Some(TemporaryAssignments::new(&values)?)
+    };
+
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
     let executable = Executable::from(&args, redirects);
-    run(executable)
+    run(executable, exec)
+}
+
+/// Print a command with its expanded words before running it, for `set -x`.
+/// The lines start with the value of `PS4`.
+fn trace(values: &[(&str, String)], args: &[String]) {
+    let words = values
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, quote(value)))
+        .chain(args.iter().map(|x| quote(x)))
+        .collect::<Vec<_>>();
+    let prefix = expand::variable("PS4").unwrap_or_else(|| String::from(DEFAULT_PS4));
+    eprintln!("{}{}", prefix, words.join(" "));
+}
+
+fn assign(values: &[(&str, String)]) -> Result<(), ExecuteError> {
+    let mut variables = VARIABLES.lock().unwrap();
+    for (name, value) in values {
+        variables
+            .set(name, value)
+            .map_err(ExecuteError::ParameterError)?;
+    }
+    Ok(())
 }
 
-fn run(executable: Executable) -> Result<ExitStatus, ExecuteError> {
-    if let Ok(execution_result) = run_internal(executable.command, executable.args) {
+/// Variables exported for the duration of a single command. The previous
+/// values are restored when this is dropped.
+struct TemporaryAssignments(Vec<(String, Saved)>);
+
+impl TemporaryAssignments {
+    fn new(values: &[(&str, String)]) -> Result<TemporaryAssignments, ExecuteError> {
+        let mut assignments = TemporaryAssignments(vec![]);
+        for (name, value) in values {
+            let mut variables = VARIABLES.lock().unwrap();
+            let saved = variables.save(name);
+            variables
+                .set(name, value)
+                .and_then(|_| variables.export(name))
+                .map_err(ExecuteError::ParameterError)?;
+            assignments.0.push((name.to_string(), saved));
+        }
+        Ok(assignments)
+    }
+}
+
+impl Drop for TemporaryAssignments {
+    fn drop(&mut self) {
+        let mut variables = VARIABLES.lock().unwrap();
+        for (name, saved) in self.0.drain(..).rev() {
+            variables.restore(&name, saved);
+        }
+    }
+}
+
+/// Builtins whose preceding variable assignments remain in effect
+const SPECIAL_BUILTINS: [&str; 13] = [
+    ":", ".", "break", "continue", "eval", "exit", "export", "readonly", "return", "set", "shift",
+    "trap", "unset",
+];
+
+fn is_declaration(command: Option<&String>) -> bool {
+    matches!(
+        command.map(String::as_str),
+        Some("declare" | "export" | "local" | "readonly")
+    )
+}
+
+fn run(executable: Executable, exec: bool) -> Result<ExitStatus, ExecuteError> {
+    let guard = executable.redirects.apply()?;
+    if executable.command == "exec" && executable.args.is_empty() {
+        guard.keep();
+        return Ok(exit_status(0));
+    }
+
+    if let Some(body) = functions::get(executable.command) {
+        call_function(&body, executable.args)
+    } else if let Ok(execution_result) = run_internal(executable.command, executable.args) {
         Ok(execution_result)
     } else {
-        use std::process::Command;
+        use nix::unistd::{setpgid, Pid};
+        use std::{os::unix::process::CommandExt, process::Command};
 
         let mut command = Command::new(executable.command);
         command.args(executable.args);
+        // A file without a `#!` line that the kernel refuses to run is a
+        // script for the shell itself
+        let is_script = |x: &io::Error| x.raw_os_error() == Some(libc::ENOEXEC);
 
-        // Parse write fd
-        for (from, to) in executable.redirects.write {
-            if from == 1 {
-                match File::create(to) {
-                    std::io::Result::Ok(file) => command.stdout(file),
-                    std::io::Result::Err(_) => {
-                        return Err(ExecuteError::StaticError("Failed to open file"))
-                    }
-                };
-            } else if from == 2 {
-                match File::create(to) {
-                    std::io::Result::Ok(file) => command.stderr(file),
-                    std::io::Result::Err(_) => {
-                        return Err(ExecuteError::StaticError("Failed to open file"))
-                    }
-                };
-            } else {
-                todo!();
-            }
+        if exec {
+            let error = match command.exec() {
+                x if is_script(&x) => script_command(&executable).exec(),
+                x => x,
+            };
+            return Ok(command_error(executable.command, error));
         }
 
-        // Parse read fd
-        for (from, to) in executable.redirects.read {
-            if from == 0 {
-                match File::open(to) {
-                    std::io::Result::Ok(file) => command.stdin(file),
-                    std::io::Result::Err(_) => {
-                        return Err(ExecuteError::StaticError("Failed to open file"))
-                    }
-                };
-            } else {
-                todo!();
+        let job_control = jobs::enabled();
+        let spawn = |command: &mut Command| {
+            if job_control {
+                unsafe {
+                    command.pre_exec(|| {
+                        jobs::enter_group(Pid::from_raw(0), true);
+                        Ok(())
+                    });
+                }
             }
+            command.spawn()
+        };
+
+        let child = match spawn(&mut command) {
+            Err(x) if is_script(&x) => spawn(&mut script_command(&executable)),
+            x => x,
+        };
+        let child = match child {
+            Ok(x) => x,
+            Err(x) => return Ok(command_error(executable.command, x)),
+        };
+        let pid = Pid::from_raw(child.id() as i32);
+        if job_control {
+            let _ = setpgid(pid, pid);
         }
 
-        match command.spawn() {
-            Ok(x) => {
-                *CURRENT_CHILD.lock().unwrap() = Some(x);
-                match CURRENT_CHILD.lock().unwrap().as_mut().unwrap().wait() {
-                    Ok(x) => Ok(x),
-                    Err(x) => Err(ExecuteError::IoError(x)),
-                }
-            }
-            Err(x) => Err(ExecuteError::IoError(x)),
+        let text = std::iter::once(executable.command)
+            .chain(executable.args.iter().copied())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let codes = jobs::run_foreground(pid, &[pid], &text);
+        Ok(exit_status(codes.last().copied().unwrap_or(1)))
+    }
+}
+
+/// A new shell running an executable file as a script, with the file found
+/// in `PATH` like the command itself
+fn script_command(executable: &Executable) -> std::process::Command {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut path = PathBuf::from(executable.command);
+    if !executable.command.contains('/') {
+        let dirs = env::var("PATH").unwrap_or_default();
+        let found = dirs
+            .split(':')
+            .filter(|x| !x.is_empty())
+            .map(|x| Path::new(x).join(executable.command))
+            .find(|x| {
+                fs::metadata(x).is_ok_and(|x| x.is_file() && x.permissions().mode() & 0o111 != 0)
+            });
+        if let Some(x) = found {
+            path = x;
+        }
+    }
+
+    let shell = env::current_exe().unwrap_or_else(|_| PathBuf::from("rush"));
+    let mut command = std::process::Command::new(shell);
+    command.arg(path).args(executable.args);
+    command
+}
+
+/// The description of an I/O error as printed by `strerror`, without the
+/// error number Rust adds to it
+pub fn error_text(error: &io::Error) -> String {
+    match error.raw_os_error() {
+        Some(x) => nix::errno::Errno::from_i32(x).desc().to_string(),
+        None => error.to_string(),
+    }
+}
+
+/// Report an external command that could not be started. The status is 127
+/// if it doesn't exist and 126 if it can't be executed.
+pub fn command_error(command: &str, error: std::io::Error) -> ExitStatus {
+    use std::io::ErrorKind;
+
+    match error.kind() {
+        ErrorKind::NotFound if command.contains('/') => {
+            eprintln!("rush: {}: No such file or directory", command);
+            exit_status(127)
+        }
+        ErrorKind::NotFound => {
+            eprintln!("rush: {}: command not found", command);
+            exit_status(127)
+        }
+        ErrorKind::PermissionDenied if std::path::Path::new(command).is_dir() => {
+            eprintln!("rush: {}: Is a directory", command);
+            exit_status(126)
+        }
+        ErrorKind::PermissionDenied => {
+            eprintln!("rush: {}: Permission denied", command);
+            exit_status(126)
+        }
+        _ => {
+            eprintln!("rush: {}: {}", command, error_text(&error));
+            exit_status(126)
         }
     }
 }
@@ -429,3 +1171,105 @@ fn run_internal<'a>(command: &'a str, args: &'a [&'a str]) -> Result<ExitStatus,
         None => Err(ExecuteError::NotAnInner),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(text: &str) -> ExitStatus {
+        execute(&prompt::parse(text).unwrap()).unwrap()
+    }
+
+    fn variable(name: &str) -> Option<String> {
+        VARIABLES.lock().unwrap().get(name)
+    }
+
+    #[test]
+    fn test_here_string() {
+        assert!(run("rush_test_here=$(cat <<< \"a  $((1 + 1))\")").success());
+        assert_eq!(variable("rush_test_here"), Some(String::from("a  2")));
+        assert!(run("rush_test_here=$(cat <<<rush_test_here_$((3)))").success());
+        assert_eq!(
+            variable("rush_test_here"),
+            Some(String::from("rush_test_here_3"))
+        );
+    }
+
+    #[test]
+    fn test_exec_redirects() {
+        let path = std::env::temp_dir().join(format!("rush_test_exec_{}", std::process::id()));
+        let path = path.display();
+        assert!(run(&format!("exec 7>{}", path)).success());
+        assert!(run("echo kept >&7").success());
+        assert!(run("exec 7>&-").success());
+        assert!(!run("echo closed >&7").success());
+        assert_eq!(std::fs::read_to_string(path.to_string()).unwrap(), "kept\n");
+        let _ = std::fs::remove_file(path.to_string());
+    }
+
+    #[test]
+    fn test_null_parameter() {
+        use conch_parser::{lexer::Lexer, parse::DefaultParser};
+
+        let text = "${rush_test_null:?oops}";
+        let word = DefaultParser::new(Lexer::new(text.chars())).word();
+        match expand_word(&word.unwrap().unwrap()) {
+            Err(ExecuteError::NullParameter(x)) => assert_eq!(x, "rush_test_null: oops"),
+            x => panic!("unexpected result: {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_substitution_status() {
+        assert_eq!(run("$(exit 4)"), exit_status(4));
+        assert_eq!(run("rush_test_status=$(exit 3)"), exit_status(3));
+        assert_eq!(run("$(exit 4) $(true)"), exit_status(0));
+    }
+
+    #[test]
+    fn test_pipe_status() {
+        assert_eq!(run("true | false"), exit_status(1));
+        assert_eq!(run("false | true"), exit_status(0));
+        // The statuses are read in a subshell, away from the other tests
+        assert!(run("rush_test_pipe=$(false | true | (exit 3); echo $PIPESTATUS $?)").success());
+        assert_eq!(variable("rush_test_pipe"), Some(String::from("1 0 3 3")));
+    }
+
+    #[test]
+    fn test_capture_output() {
+        assert!(run("rush_test_capture=$(echo a; echo; echo)").success());
+        assert_eq!(variable("rush_test_capture"), Some(String::from("a")));
+        assert!(run("rush_test_capture=\"$(printf 'a\\n\\nb\\n\\n')\"").success());
+        assert_eq!(variable("rush_test_capture"), Some(String::from("a\n\nb")));
+
+        assert!(run("rush_test_capture=$(echo $(echo in)side `echo back`)").success());
+        assert_eq!(
+            variable("rush_test_capture"),
+            Some(String::from("inside back"))
+        );
+
+        assert_eq!(run("rush_test_capture=$(exit 5)"), exit_status(5));
+        assert!(run("rush_test_capture=$( (exit 6); echo $?)").success());
+        assert_eq!(variable("rush_test_capture"), Some(String::from("6")));
+    }
+
+    #[test]
+    fn test_and_or_status() {
+        assert!(run("false || rush_test_and_or=$?").success());
+        assert_eq!(variable("rush_test_and_or"), Some(String::from("1")));
+        assert!(run("(exit 3) || rush_test_and_or=$?").success());
+        assert_eq!(variable("rush_test_and_or"), Some(String::from("3")));
+        assert!(run("true && rush_test_and_or=$?").success());
+        assert_eq!(variable("rush_test_and_or"), Some(String::from("0")));
+    }
+
+    #[test]
+    fn test_arithmetic_shift() {
+        // Read line by line, so a `<<` taken for a here-document would
+        // swallow the following lines
+        let text = "rush_test_shift=$((1 << 4))\n((rush_test_shift <<= 1))\nrush_test_shift=$((rush_test_shift<<1))\n";
+        let mut reader = ScriptReader::new(Box::new(io::Cursor::new(text.as_bytes().to_vec())));
+        assert!(execute_input(&Prompt::default(), &mut reader, false).success());
+        assert_eq!(variable("rush_test_shift"), Some(String::from("64")));
+    }
+}
@@ -0,0 +1,315 @@
+use super::{word, ExecuteError};
+use crate::{
+    globals::{self, HERE_STRING_MARKER},
+    options::OPTIONS,
+};
+use conch_parser::ast::{Redirect, TopLevelWord};
+use nix::{
+    fcntl::{self, fcntl, FcntlArg, FdFlag, OFlag},
+    sys::stat::Mode,
+    unistd::{close, dup2},
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::unix::io::{IntoRawFd, RawFd},
+};
+
+/// File descriptors saved by a redirection are moved above this number so they
+/// don't collide with descriptors the user is likely to redirect
+const SAVED_FD_BASE: RawFd = 10;
+
+/// Run `f` while the free descriptors below `SAVED_FD_BASE` are taken, so the
+/// ones it opens for the shell's own use can't be replaced by a redirection
+pub fn above_user_fds<T>(f: impl FnOnce() -> T) -> T {
+    let mut placeholders = vec![];
+    while let Ok(fd) = fcntl::open(
+        "/dev/null",
+        OFlag::O_RDONLY | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    ) {
+        if fd >= SAVED_FD_BASE {
+            let _ = close(fd);
+            break;
+        }
+        placeholders.push(fd);
+    }
+
+    let result = f();
+    for fd in placeholders {
+        let _ = close(fd);
+    }
+    result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OpenMode {
+    Read,
+    Write,
+    Append,
+    ReadWrite,
+    Clobber,
+}
+
+#[derive(Debug, PartialEq)]
+enum RedirectAction {
+    Open(RawFd, String, OpenMode),
+    Dup(RawFd, RawFd),
+    Close(RawFd),
+    Heredoc(RawFd, String),
+}
+
+/// An ordered list of file descriptor operations. The operations are applied
+/// left to right, so `>file 2>&1` and `2>&1 >file` behave differently.
+#[derive(Debug, Default)]
+pub struct Redirects {
+    actions: Vec<RedirectAction>,
+}
+
+impl Redirects {
+    pub fn parse(&mut self, redirect: &Redirect<TopLevelWord<String>>) -> Result<(), ExecuteError> {
+        let stdin = globals::STDIN as RawFd;
+        let stdout = globals::STDOUT as RawFd;
+        let fd = |from: &Option<u16>, default: RawFd| from.map(RawFd::from).unwrap_or(default);
+
+        let action = match redirect {
+            // A here-string is not split or matched against files
+            Redirect::Read(from, to) if word::is_here_string(to) => {
+                let text = word::string(to)?;
+                let text = text.trim_start_matches(HERE_STRING_MARKER);
+                RedirectAction::Heredoc(fd(from, stdin), format!("{}\n", text))
+            }
+            Redirect::Read(from, to) => {
+                RedirectAction::Open(fd(from, stdin), word::target(to)?, OpenMode::Read)
+            }
+            Redirect::Write(from, to) => {
+                RedirectAction::Open(fd(from, stdout), word::target(to)?, OpenMode::Write)
+            }
+            Redirect::ReadWrite(from, to) => {
+                RedirectAction::Open(fd(from, stdin), word::target(to)?, OpenMode::ReadWrite)
+            }
+            Redirect::Append(from, to) => {
+                RedirectAction::Open(fd(from, stdout), word::target(to)?, OpenMode::Append)
+            }
+            Redirect::Clobber(from, to) => {
+                RedirectAction::Open(fd(from, stdout), word::target(to)?, OpenMode::Clobber)
+            }
+            Redirect::Heredoc(from, body) => {
+                RedirectAction::Heredoc(fd(from, stdin), word::string(body)?)
+            }
+            Redirect::DupRead(from, to) => dup_action(fd(from, stdin), &word::string(to)?)?,
+            Redirect::DupWrite(from, to) => dup_action(fd(from, stdout), &word::string(to)?)?,
+        };
+
+        self.actions.push(action);
+        Ok(())
+    }
+
+    /// Apply the redirections to the file descriptors of the shell itself. The
+    /// returned guard restores the original descriptors when dropped, which lets
+    /// builtins, compound commands and spawned processes share one code path.
+    pub fn apply(&self) -> Result<RedirectGuard, ExecuteError> {
+        let mut guard = RedirectGuard::default();
+
+        for action in &self.actions {
+            match action {
+                RedirectAction::Open(fd, path, mode) => {
+                    let file = open(path, *mode)
+                        .map_err(|x| ExecuteError::RedirectError(path.to_string(), x))?;
+                    guard.save(*fd);
+                    install(file, *fd)
+                        .map_err(|x| ExecuteError::RedirectError(path.to_string(), x))?;
+                }
+                RedirectAction::Dup(fd, source) => {
+                    if fcntl(*source, FcntlArg::F_GETFD).is_err() {
+                        return Err(ExecuteError::BadFileDescriptor(*source));
+                    }
+                    if fd != source {
+                        guard.save(*fd);
+                        dup2(*source, *fd).map_err(|_| ExecuteError::BadFileDescriptor(*fd))?;
+                    }
+                }
+                RedirectAction::Close(fd) => {
+                    guard.save(*fd);
+                    let _ = close(*fd);
+                }
+                RedirectAction::Heredoc(fd, body) => {
+                    let file = heredoc_file(body).map_err(ExecuteError::IoError)?;
+                    guard.save(*fd);
+                    install(file, *fd).map_err(ExecuteError::IoError)?;
+                }
+            }
+        }
+
+        Ok(guard)
+    }
+}
+
+fn dup_action(fd: RawFd, word: &str) -> Result<RedirectAction, ExecuteError> {
+    if word == "-" {
+        Ok(RedirectAction::Close(fd))
+    } else {
+        match word.parse::<RawFd>() {
+            Ok(source) => Ok(RedirectAction::Dup(fd, source)),
+            Err(_) => Err(ExecuteError::AmbiguousRedirect(word.to_string())),
+        }
+    }
+}
+
+fn open(path: &str, mode: OpenMode) -> std::io::Result<File> {
+    // With noclobber only `>|` may truncate an existing regular file
+    if mode == OpenMode::Write
+        && OPTIONS.lock().unwrap().noclobber
+        && fs::metadata(path).is_ok_and(|x| x.is_file())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "cannot overwrite existing file",
+        ));
+    }
+
+    let mut options = OpenOptions::new();
+    match mode {
+        OpenMode::Read => options.read(true),
+        OpenMode::Write | OpenMode::Clobber => options.write(true).create(true).truncate(true),
+        OpenMode::Append => options.append(true).create(true),
+        OpenMode::ReadWrite => options.read(true).write(true).create(true),
+    };
+    options.open(path)
+}
+
+/// Store a here-document body in an unlinked temporary file positioned at
+/// its start, so bodies of any size can be read without blocking the shell
+fn heredoc_file(body: &str) -> std::io::Result<File> {
+    use std::io::{Seek, SeekFrom};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let path = std::env::temp_dir().join(format!(
+        "rush-heredoc-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    let _ = std::fs::remove_file(&path);
+    file.write_all(body.as_bytes())?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+/// Move an opened file onto `fd`, keeping it open across exec
+fn install(file: File, fd: RawFd) -> std::io::Result<()> {
+    let raw = file.into_raw_fd();
+    if raw == fd {
+        fcntl(fd, FcntlArg::F_SETFD(FdFlag::empty())).map_err(nix_to_io)?;
+        return Ok(());
+    }
+
+    let result = dup2(raw, fd).map(|_| ()).map_err(nix_to_io);
+    let _ = close(raw);
+    result
+}
+
+fn nix_to_io(err: nix::Error) -> std::io::Error {
+    match err.as_errno() {
+        Some(errno) => std::io::Error::from_raw_os_error(errno as i32),
+        None => std::io::Error::other(err.to_string()),
+    }
+}
+
+/// Restores the descriptors touched by [`Redirects::apply`] on drop
+#[derive(Debug, Default)]
+pub struct RedirectGuard {
+    saved: Vec<(RawFd, Option<RawFd>)>,
+}
+
+impl RedirectGuard {
+    /// Keep the redirections in effect, as done by `exec` without a command
+    pub fn keep(mut self) {
+        flush_std_streams();
+        for (_, copy) in self.saved.drain(..) {
+            if let Some(copy) = copy {
+                let _ = close(copy);
+            }
+        }
+    }
+
+    fn save(&mut self, fd: RawFd) {
+        if self.saved.iter().any(|(x, _)| *x == fd) {
+            return;
+        }
+
+        flush_std_streams();
+        let copy = fcntl(fd, FcntlArg::F_DUPFD_CLOEXEC(SAVED_FD_BASE)).ok();
+        self.saved.push((fd, copy));
+    }
+}
+
+impl Drop for RedirectGuard {
+    fn drop(&mut self) {
+        flush_std_streams();
+        for (fd, copy) in self.saved.drain(..).rev() {
+            match copy {
+                Some(copy) => {
+                    let _ = dup2(copy, fd);
+                    let _ = close(copy);
+                }
+                None => {
+                    let _ = close(fd);
+                }
+            }
+        }
+    }
+}
+
+fn flush_std_streams() {
+    let _ = std::io::stdout().flush();
+    let _ = std::io::stderr().flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dup_action() {
+        assert_eq!(dup_action(2, "1").unwrap(), RedirectAction::Dup(2, 1));
+        assert_eq!(dup_action(1, "-").unwrap(), RedirectAction::Close(1));
+        assert!(dup_action(1, "file").is_err());
+    }
+
+    #[test]
+    fn test_redirect_order() {
+        use std::io::Read;
+
+        let path = std::env::temp_dir().join(format!("rush_redirect_{}", std::process::id()));
+        let path_str = path.to_str().unwrap().to_string();
+
+        let redirects = Redirects {
+            actions: vec![
+                RedirectAction::Open(20, path_str.clone(), OpenMode::Write),
+                RedirectAction::Dup(21, 20),
+            ],
+        };
+        {
+            let _guard = redirects.apply().unwrap();
+            assert!(fcntl(21, FcntlArg::F_GETFD).is_ok());
+            nix::unistd::write(21, b"hello").unwrap();
+        }
+        assert!(fcntl(20, FcntlArg::F_GETFD).is_err());
+        assert!(fcntl(21, FcntlArg::F_GETFD).is_err());
+
+        let mut content = String::new();
+        File::open(&path)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "hello");
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,484 @@
+//! Convert parsed commands back into shell source, e.g. for `declare -f`
+use super::PipeCommand;
+use conch_parser::ast::*;
+
+const INDENT: &str = "    ";
+
+/// Format a function definition the way `declare -f` prints it
+pub fn function(name: &str, body: &DefaultCompoundCommand) -> String {
+    let mut out = format!("{} () ", name);
+    compound(&mut out, body, 0);
+    out
+}
+
+/// Format a single command on one line
+pub fn command(command: &TopLevelCommand<String>) -> String {
+    let mut out = String::new();
+    toplevel(&mut out, command, 0);
+    out
+}
+
+/// Format an and-or list, such as a command started in the background
+pub fn list(list: &AndOrList<ListableCommand<PipeCommand>>) -> String {
+    let mut out = String::new();
+    and_or(&mut out, list, 0);
+    out
+}
+
+/// Format the stages of a pipeline
+pub fn pipeline(commands: &[PipeCommand]) -> String {
+    let mut out = String::new();
+    for (index, command) in commands.iter().enumerate() {
+        if index > 0 {
+            out.push_str(" | ");
+        }
+        pipeable(&mut out, command, 0);
+    }
+    out
+}
+
+fn push_indent(out: &mut String, level: usize) {
+    for _ in 0..level {
+        out.push_str(INDENT);
+    }
+}
+
+/// Write the commands one per line, each prefixed by the indentation
+fn body(out: &mut String, commands: &[TopLevelCommand<String>], level: usize) {
+    for command in commands {
+        push_indent(out, level);
+        toplevel(out, command, level);
+        out.push('\n');
+    }
+}
+
+fn toplevel(out: &mut String, command: &TopLevelCommand<String>, level: usize) {
+    match &command.0 {
+        Command::List(list) => and_or(out, list, level),
+        Command::Job(list) => {
+            and_or(out, list, level);
+            out.push_str(" &");
+        }
+    }
+}
+
+fn and_or(out: &mut String, list: &AndOrList<ListableCommand<PipeCommand>>, level: usize) {
+    listable(out, &list.first, level);
+    for command in &list.rest {
+        let (operator, command) = match command {
+            AndOr::And(x) => (" && ", x),
+            AndOr::Or(x) => (" || ", x),
+        };
+        out.push_str(operator);
+        listable(out, command, level);
+    }
+}
+
+fn listable(out: &mut String, command: &ListableCommand<PipeCommand>, level: usize) {
+    match command {
+        ListableCommand::Single(command) => pipeable(out, command, level),
+        ListableCommand::Pipe(negate, commands) => {
+            if *negate {
+                out.push_str("! ");
+            }
+            for (index, command) in commands.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(" | ");
+                }
+                pipeable(out, command, level);
+            }
+        }
+    }
+}
+
+fn pipeable(out: &mut String, command: &PipeCommand, level: usize) {
+    match command {
+        PipeableCommand::Simple(command) => simple(out, command),
+        PipeableCommand::Compound(command) => compound(out, command, level),
+        PipeableCommand::FunctionDef(name, body) => {
+            out.push_str(name);
+            out.push_str(" () ");
+            compound(out, body, level);
+        }
+    }
+}
+
+fn simple(out: &mut String, command: &DefaultSimpleCommand) {
+    let mut parts = vec![];
+    for prefix in &command.redirects_or_env_vars {
+        parts.push(match prefix {
+            RedirectOrEnvVar::Redirect(x) => redirect(x),
+            RedirectOrEnvVar::EnvVar(name, value) => {
+                format!("{}={}", name, value.as_ref().map(word).unwrap_or_default())
+            }
+        });
+    }
+    for item in &command.redirects_or_cmd_words {
+        parts.push(match item {
+            RedirectOrCmdWord::Redirect(x) => redirect(x),
+            RedirectOrCmdWord::CmdWord(x) => word(x),
+        });
+    }
+    out.push_str(&parts.join(" "));
+}
+
+fn compound(out: &mut String, command: &DefaultCompoundCommand, level: usize) {
+    match &command.kind {
+        CompoundCommandKind::Brace(commands) => {
+            out.push_str("{\n");
+            body(out, commands, level + 1);
+            push_indent(out, level);
+            out.push('}');
+        }
+        CompoundCommandKind::Subshell(commands) => {
+            out.push_str("(\n");
+            body(out, commands, level + 1);
+            push_indent(out, level);
+            out.push(')');
+        }
+        CompoundCommandKind::While(pair) => guarded_loop(out, "while", pair, level),
+        CompoundCommandKind::Until(pair) => guarded_loop(out, "until", pair, level),
+        CompoundCommandKind::If {
+            conditionals,
+            else_branch,
+        } => {
+            for (index, GuardBodyPair { guard, body: then }) in conditionals.iter().enumerate() {
+                if index > 0 {
+                    push_indent(out, level);
+                    out.push_str("elif ");
+                } else {
+                    out.push_str("if ");
+                }
+                inline(out, guard, level);
+                out.push_str("; then\n");
+                body(out, then, level + 1);
+            }
+            if let Some(commands) = else_branch {
+                push_indent(out, level);
+                out.push_str("else\n");
+                body(out, commands, level + 1);
+            }
+            push_indent(out, level);
+            out.push_str("fi");
+        }
+        CompoundCommandKind::For {
+            var,
+            words,
+            body: commands,
+        } => {
+            out.push_str("for ");
+            out.push_str(var);
+            if let Some(words) = words {
+                out.push_str(" in");
+                for x in words {
+                    out.push(' ');
+                    out.push_str(&word(x));
+                }
+            }
+            out.push_str("; do\n");
+            body(out, commands, level + 1);
+            push_indent(out, level);
+            out.push_str("done");
+        }
+        CompoundCommandKind::Case {
+            word: subject,
+            arms,
+        } => {
+            out.push_str("case ");
+            out.push_str(&word(subject));
+            out.push_str(" in\n");
+            for PatternBodyPair {
+                patterns,
+                body: commands,
+            } in arms
+            {
+                push_indent(out, level + 1);
+                out.push_str(&patterns.iter().map(word).collect::<Vec<_>>().join(" | "));
+                out.push_str(")\n");
+                body(out, commands, level + 2);
+                push_indent(out, level + 2);
+                out.push_str(";;\n");
+            }
+            push_indent(out, level);
+            out.push_str("esac");
+        }
+    }
+
+    for x in &command.io {
+        out.push(' ');
+        out.push_str(&redirect(x));
+    }
+}
+
+fn guarded_loop(
+    out: &mut String,
+    keyword: &str,
+    pair: &GuardBodyPair<TopLevelCommand<String>>,
+    level: usize,
+) {
+    out.push_str(keyword);
+    out.push(' ');
+    inline(out, &pair.guard, level);
+    out.push_str("; do\n");
+    body(out, &pair.body, level + 1);
+    push_indent(out, level);
+    out.push_str("done");
+}
+
+/// Write commands separated by semicolons on a single line
+fn inline(out: &mut String, commands: &[TopLevelCommand<String>], level: usize) {
+    for (index, command) in commands.iter().enumerate() {
+        if index > 0 {
+            out.push_str("; ");
+        }
+        toplevel(out, command, level);
+    }
+}
+
+fn redirect(redirect: &Redirect<TopLevelWord<String>>) -> String {
+    let (fd, operator, target) = match redirect {
+        Redirect::Read(fd, x) => (fd, "<", x),
+        Redirect::Write(fd, x) => (fd, ">", x),
+        Redirect::ReadWrite(fd, x) => (fd, "<>", x),
+        Redirect::Append(fd, x) => (fd, ">>", x),
+        Redirect::Clobber(fd, x) => (fd, ">|", x),
+        Redirect::DupRead(fd, x) => (fd, "<&", x),
+        Redirect::DupWrite(fd, x) => (fd, ">&", x),
+        Redirect::Heredoc(fd, x) => {
+            let mut body = heredoc_body(x);
+            if !body.ends_with('\n') {
+                body.push('\n');
+            }
+            let mut delimiter = String::from("EOF");
+            let mut suffix = 0;
+            while body.lines().any(|x| x == delimiter) {
+                suffix += 1;
+                delimiter = format!("EOF{}", suffix);
+            }
+            return format!(
+                "{}<<{}\n{}{}",
+                fd.map(|x| x.to_string()).unwrap_or_default(),
+                delimiter,
+                body,
+                delimiter
+            );
+        }
+    };
+    format!(
+        "{}{}{}",
+        fd.map(|x| x.to_string()).unwrap_or_default(),
+        operator,
+        word(target)
+    )
+}
+
+/// The body of a here-document with an unquoted delimiter, in which the
+/// expansions stay and the literal characters that would start one are escaped
+fn heredoc_body(body: &TopLevelWord<String>) -> String {
+    let words = match &body.0 {
+        ComplexWord::Single(x) => std::slice::from_ref(x),
+        ComplexWord::Concat(words) => words.as_slice(),
+    };
+    words
+        .iter()
+        .map(|x| match x {
+            Word::Simple(SimpleWord::Literal(text)) => text
+                .chars()
+                .flat_map(|c| match c {
+                    '\\' | '$' | '`' => vec!['\\', c],
+                    c => vec![c],
+                })
+                .collect(),
+            x => single_word(x),
+        })
+        .collect()
+}
+
+pub fn word(word: &TopLevelWord<String>) -> String {
+    match &word.0 {
+        ComplexWord::Single(x) => single_word(x),
+        ComplexWord::Concat(words) => words.iter().map(single_word).collect(),
+    }
+}
+
+fn single_word(word: &DefaultWord) -> String {
+    match word {
+        Word::Simple(x) => simple_word(x, false),
+        Word::SingleQuoted(x) => format!("'{}'", x),
+        Word::DoubleQuoted(words) => format!(
+            "\"{}\"",
+            words
+                .iter()
+                .map(|x| simple_word(x, true))
+                .collect::<String>()
+        ),
+    }
+}
+
+fn simple_word(word: &DefaultSimpleWord, quoted: bool) -> String {
+    match word {
+        SimpleWord::Literal(x) => x.to_string(),
+        SimpleWord::Escaped(x) if quoted => x.to_string(),
+        SimpleWord::Escaped(x) => format!("\\{}", x),
+        SimpleWord::Param(x) => x.to_string(),
+        SimpleWord::Subst(x) => substitution(x),
+        SimpleWord::Star => "*".to_string(),
+        SimpleWord::Question => "?".to_string(),
+        SimpleWord::SquareOpen => "[".to_string(),
+        SimpleWord::SquareClose => "]".to_string(),
+        SimpleWord::Tilde => "~".to_string(),
+        SimpleWord::Colon => ":".to_string(),
+    }
+}
+
+fn parameter_name(parameter: &Parameter<String>) -> String {
+    let name = parameter.to_string();
+    name.trim_start_matches('$')
+        .trim_start_matches('{')
+        .trim_end_matches('}')
+        .to_string()
+}
+
+fn substitution(substitution: &DefaultParameterSubstitution) -> String {
+    let operation = |parameter: &Parameter<String>, colon: bool, op: &str, x: &Option<_>| {
+        format!(
+            "${{{}{}{}{}}}",
+            parameter_name(parameter),
+            if colon { ":" } else { "" },
+            op,
+            x.as_ref().map(word).unwrap_or_default()
+        )
+    };
+
+    match substitution {
+        ParameterSubstitution::Command(commands) => format!(
+            "$({})",
+            commands.iter().map(command).collect::<Vec<_>>().join("; ")
+        ),
+        ParameterSubstitution::Len(x) => format!("${{#{}}}", parameter_name(x)),
+        ParameterSubstitution::Arith(x) => {
+            format!("$(({}))", x.as_ref().map(arithmetic).unwrap_or_default())
+        }
+        ParameterSubstitution::Default(colon, p, x) => operation(p, *colon, "-", x),
+        ParameterSubstitution::Assign(colon, p, x) => operation(p, *colon, "=", x),
+        ParameterSubstitution::Error(colon, p, x) => operation(p, *colon, "?", x),
+        ParameterSubstitution::Alternative(colon, p, x) => operation(p, *colon, "+", x),
+        ParameterSubstitution::RemoveSmallestSuffix(p, x) => operation(p, false, "%", x),
+        ParameterSubstitution::RemoveLargestSuffix(p, x) => operation(p, false, "%%", x),
+        ParameterSubstitution::RemoveSmallestPrefix(p, x) => operation(p, false, "#", x),
+        ParameterSubstitution::RemoveLargestPrefix(p, x) => operation(p, false, "##", x),
+    }
+}
+
+pub fn arithmetic(expression: &DefaultArithmetic) -> String {
+    use Arithmetic::*;
+
+    let binary = |a: &DefaultArithmetic, op: &str, b: &DefaultArithmetic| {
+        format!("({} {} {})", arithmetic(a), op, arithmetic(b))
+    };
+
+    match expression {
+        Var(x) => x.to_string(),
+        Literal(x) => x.to_string(),
+        Pow(a, b) => binary(a, "**", b),
+        PostIncr(x) => format!("{}++", x),
+        PostDecr(x) => format!("{}--", x),
+        PreIncr(x) => format!("++{}", x),
+        PreDecr(x) => format!("--{}", x),
+        UnaryPlus(x) => format!("+{}", arithmetic(x)),
+        UnaryMinus(x) => format!("-{}", arithmetic(x)),
+        LogicalNot(x) => format!("!{}", arithmetic(x)),
+        BitwiseNot(x) => format!("~{}", arithmetic(x)),
+        Mult(a, b) => binary(a, "*", b),
+        Div(a, b) => binary(a, "/", b),
+        Modulo(a, b) => binary(a, "%", b),
+        Add(a, b) => binary(a, "+", b),
+        Sub(a, b) => binary(a, "-", b),
+        ShiftLeft(a, b) => binary(a, "<<", b),
+        ShiftRight(a, b) => binary(a, ">>", b),
+        Less(a, b) => binary(a, "<", b),
+        LessEq(a, b) => binary(a, "<=", b),
+        Great(a, b) => binary(a, ">", b),
+        GreatEq(a, b) => binary(a, ">=", b),
+        Eq(a, b) => binary(a, "==", b),
+        NotEq(a, b) => binary(a, "!=", b),
+        BitwiseAnd(a, b) => binary(a, "&", b),
+        BitwiseXor(a, b) => binary(a, "^", b),
+        BitwiseOr(a, b) => binary(a, "|", b),
+        LogicalAnd(a, b) => binary(a, "&&", b),
+        LogicalOr(a, b) => binary(a, "||", b),
+        Ternary(a, b, c) => format!(
+            "({} ? {} : {})",
+            arithmetic(a),
+            arithmetic(b),
+            arithmetic(c)
+        ),
+        Assign(x, value) => format!("{} = {}", x, arithmetic(value)),
+        Sequence(items) => items.iter().map(arithmetic).collect::<Vec<_>>().join(", "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use conch_parser::{lexer::Lexer, parse::DefaultParser};
+
+    fn parse(source: &str) -> Vec<TopLevelCommand<String>> {
+        DefaultParser::new(Lexer::new(source.chars()))
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let sources = [
+            "echo hello 'single' \"double ${x}\" >out 2>&1",
+            "a && b || ! c | d &",
+            "FOO=1 env",
+            "echo ${x:-default} ${#y} $(pwd) $(((1 + 2)))",
+        ];
+        for source in sources.iter() {
+            let commands = parse(source);
+            let printed = command(&commands[0]);
+            assert_eq!(&printed, source);
+            assert_eq!(parse(&printed), commands);
+        }
+    }
+
+    #[test]
+    fn test_function() {
+        let source =
+            "f() { if true; then echo a; else echo b; fi; for x in 1 2; do echo ${x}; done; }";
+        let commands = parse(source);
+        let body = match &commands[0].0 {
+            Command::List(AndOrList {
+                first: ListableCommand::Single(PipeableCommand::FunctionDef(_, body)),
+                ..
+            }) => body.clone(),
+            _ => panic!("Expected a function definition"),
+        };
+        let printed = function("f", &body);
+        assert_eq!(
+            printed,
+            "f () {\n    if true; then\n        echo a\n    else\n        echo b\n    fi\n    \
+             for x in 1 2; do\n        echo ${x}\n    done\n}"
+        );
+        assert_eq!(parse(&printed)[0], commands[0]);
+    }
+
+    #[test]
+    fn test_heredoc() {
+        let source = "cat <<EOF\nbody $1 \\$x\nEOF\n";
+        let commands = parse(source);
+        let printed = command(&commands[0]);
+        assert_eq!(printed, "cat <<EOF\nbody $1 \\$x\nEOF");
+        assert_eq!(parse(&printed), commands);
+
+        let commands = parse("cat <<'END'\nEOF\n$x `y` \\\nEND\n");
+        assert_eq!(
+            command(&commands[0]),
+            "cat <<EOF1\nEOF\n\\$x \\`y\\` \\\\\nEOF1"
+        );
+    }
+}
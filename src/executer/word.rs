@@ -0,0 +1,509 @@
+use super::{expand, unparse, ExecuteError};
+use crate::{
+    glob, globals::HERE_STRING_MARKER, libc_bindings::user_home_dir_by_user_name, options::OPTIONS,
+    variables::is_valid_name,
+};
+use conch_parser::ast::{
+    ComplexWord, DefaultSimpleWord, DefaultWord, Parameter, ParameterSubstitution, SimpleWord,
+    TopLevelWord, Word,
+};
+
+/// Field separators used when `IFS` is unset
+const DEFAULT_IFS: &str = " \t\n";
+
+/// A piece of a word after parameter expansion, command substitution and
+/// arithmetic expansion, remembering what the remaining steps apply to
+#[derive(Debug, PartialEq)]
+enum Part {
+    /// Text taken as it is, because it was quoted or written in the word itself
+    Literal(String),
+    /// An unquoted `*`, `?`, `[` or `]`
+    Pattern(char),
+    /// The unquoted result of an expansion, which is split into fields
+    Expanded(String),
+    /// An unquoted `$@` or `$*`, where each positional parameter is split
+    ExpandedFields(Vec<String>),
+    /// A quoted `"$@"`, which keeps every positional parameter a separate field
+    QuotedFields(Vec<String>),
+}
+
+/// Expand a word into the fields it produces, such as the arguments of a
+/// command. The expanded parts are split on `IFS`, and fields with unquoted
+/// pattern characters are replaced by the matching paths.
+pub fn fields(word: &TopLevelWord<String>) -> Result<Vec<String>, ExecuteError> {
+    let parts = parts(word, false)?;
+    let ifs = ifs();
+    let mut splitter = Splitter::new(&ifs);
+    for part in parts {
+        splitter.push(part);
+    }
+
+    let mut fields = vec![];
+    for field in splitter.finish() {
+        if field.magic {
+            fields.extend(expand_pattern(&field)?);
+        } else {
+            fields.push(field.text);
+        }
+    }
+    Ok(fields)
+}
+
+/// Expand a word without field splitting or globbing, as done for variable
+/// assignments, here-documents and the word of a `case` command
+pub fn string(word: &TopLevelWord<String>) -> Result<String, ExecuteError> {
+    Ok(join(parts(word, false)?))
+}
+
+/// Expand a variable assignment, or the value assigned to it. Like
+/// [`string`], but a tilde is also expanded after the `=` and after every
+/// unquoted `:`, as in `PATH=~/bin:~/.local/bin`.
+pub fn assignment(word: &TopLevelWord<String>) -> Result<String, ExecuteError> {
+    Ok(join(parts(word, true)?))
+}
+
+/// Return true if the word has the form `name=value`
+pub fn is_assignment(word: &TopLevelWord<String>) -> bool {
+    let mut text = String::new();
+    for word in words(word) {
+        match word {
+            Word::Simple(SimpleWord::Literal(x)) => text.push_str(x),
+            _ => break,
+        }
+    }
+
+    match text.find('=') {
+        Some(index) => is_valid_name(&text[..index]),
+        None => false,
+    }
+}
+
+/// Return true if the word contains a command substitution
+pub fn has_substitution(word: &TopLevelWord<String>) -> bool {
+    let is_substitution = |word: &DefaultSimpleWord| {
+        matches!(
+            word,
+            SimpleWord::Subst(x) if matches!(**x, ParameterSubstitution::Command(_))
+        )
+    };
+
+    words(word).iter().any(|word| match word {
+        Word::Simple(x) => is_substitution(x),
+        Word::DoubleQuoted(words) => words.iter().any(is_substitution),
+        Word::SingleQuoted(_) => false,
+    })
+}
+
+fn join(parts: Vec<Part>) -> String {
+    parts
+        .into_iter()
+        .map(|part| match part {
+            Part::Literal(x) | Part::Expanded(x) => x,
+            Part::Pattern(x) => x.to_string(),
+            Part::ExpandedFields(x) | Part::QuotedFields(x) => x.join(" "),
+        })
+        .collect()
+}
+
+/// Expand a word into the syntax understood by [`glob::matches`]. Quoted
+/// parts are escaped, while unquoted expansions may contain patterns.
+pub fn pattern(word: &TopLevelWord<String>) -> Result<String, ExecuteError> {
+    Ok(parts(word, false)?
+        .into_iter()
+        .map(|part| match part {
+            Part::Literal(x) => glob::escape(&x),
+            Part::Pattern(x) => x.to_string(),
+            Part::Expanded(x) => x,
+            Part::ExpandedFields(x) => x.join(" "),
+            Part::QuotedFields(x) => glob::escape(&x.join(" ")),
+        })
+        .collect())
+}
+
+/// Expand the target of a redirection, which must be exactly one field
+pub fn target(word: &TopLevelWord<String>) -> Result<String, ExecuteError> {
+    let mut fields = fields(word)?;
+    match fields.len() {
+        1 => Ok(fields.remove(0)),
+        _ => Err(ExecuteError::AmbiguousRedirect(unparse::word(word))),
+    }
+}
+
+/// Return true if the word is the target of a here-string, as marked by
+/// `prompt::heredoc::rewrite_here_strings`
+pub fn is_here_string(word: &TopLevelWord<String>) -> bool {
+    matches!(
+        words(word).first(),
+        Some(Word::Simple(SimpleWord::Literal(x))) if x.starts_with(HERE_STRING_MARKER)
+    )
+}
+
+fn ifs() -> String {
+    expand::variable("IFS").unwrap_or_else(|| String::from(DEFAULT_IFS))
+}
+
+fn words(word: &TopLevelWord<String>) -> &[DefaultWord] {
+    match &word.0 {
+        ComplexWord::Single(word) => std::slice::from_ref(word),
+        ComplexWord::Concat(words) => words.as_slice(),
+    }
+}
+
+fn parts(word: &TopLevelWord<String>, assignment: bool) -> Result<Vec<Part>, ExecuteError> {
+    let words = words(word);
+    let mut parts = vec![];
+    let mut index = 0;
+    while index < words.len() {
+        let tilde_allowed = index == 0
+            || (assignment && (words[index - 1] == COLON || is_name_prefix(&words[..index])));
+        if tilde_allowed && words[index] == TILDE {
+            index += 1 + tilde(&words[index + 1..], assignment, &mut parts);
+            continue;
+        }
+
+        word_parts(&words[index], &mut parts)?;
+        index += 1;
+    }
+    Ok(parts)
+}
+
+/// Return true if the words are the `name=` starting an assignment
+fn is_name_prefix(words: &[DefaultWord]) -> bool {
+    let mut text = String::new();
+    for word in words {
+        match word {
+            Word::Simple(SimpleWord::Literal(x)) => text.push_str(x),
+            _ => return false,
+        }
+    }
+
+    text.strip_suffix('=').is_some_and(is_valid_name)
+}
+
+const TILDE: DefaultWord = Word::Simple(SimpleWord::Tilde);
+const COLON: DefaultWord = Word::Simple(SimpleWord::Colon);
+
+/// Expand a tilde prefix, the unquoted characters after a `~` up to the first
+/// `/`. Returns the number of words following the tilde that were used.
+fn tilde(words: &[DefaultWord], assignment: bool, parts: &mut Vec<Part>) -> usize {
+    let mut prefix = String::new();
+    let mut rest = None;
+    let mut used = 0;
+    for word in words {
+        match word {
+            Word::Simple(SimpleWord::Literal(x)) => {
+                used += 1;
+                if let Some(index) = x.find('/') {
+                    prefix.push_str(&x[..index]);
+                    rest = Some(x[index..].to_string());
+                    break;
+                }
+                prefix.push_str(x);
+            }
+            _ => break,
+        }
+    }
+
+    // The prefix must end the word, or be followed by a slash or by the colon
+    // separating values in an assignment
+    let terminated = rest.is_some() || used == words.len() || (assignment && words[used] == COLON);
+    let home = match prefix.as_str() {
+        _ if !terminated => None,
+        "" => expand::variable("HOME"),
+        "+" => expand::variable("PWD"),
+        "-" => expand::variable("OLDPWD"),
+        user => user_home_dir_by_user_name(user).ok(),
+    };
+
+    parts.push(Part::Literal(home.unwrap_or(format!("~{}", prefix))));
+    parts.extend(rest.map(Part::Literal));
+    used
+}
+
+fn word_parts(word: &DefaultWord, parts: &mut Vec<Part>) -> Result<(), ExecuteError> {
+    match word {
+        Word::Simple(word) => parts.push(simple_part(word, false)?),
+        Word::SingleQuoted(x) => parts.push(Part::Literal(x.to_string())),
+        Word::DoubleQuoted(words) => {
+            // Quotes always produce a field, even an empty one, except for a
+            // lone "$@" without positional parameters
+            if !matches!(words.as_slice(), [SimpleWord::Param(Parameter::At)]) {
+                parts.push(Part::Literal(String::new()));
+            }
+            for word in words {
+                parts.push(simple_part(word, true)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn simple_part(word: &DefaultSimpleWord, quoted: bool) -> Result<Part, ExecuteError> {
+    let expanded = |x| {
+        if quoted {
+            Part::Literal(x)
+        } else {
+            Part::Expanded(x)
+        }
+    };
+
+    Ok(match word {
+        SimpleWord::Literal(x) | SimpleWord::Escaped(x) => Part::Literal(x.to_string()),
+        SimpleWord::Colon => Part::Literal(String::from(":")),
+        SimpleWord::Tilde => Part::Literal(String::from("~")),
+        SimpleWord::Star if !quoted => Part::Pattern('*'),
+        SimpleWord::Question if !quoted => Part::Pattern('?'),
+        SimpleWord::SquareOpen if !quoted => Part::Pattern('['),
+        SimpleWord::SquareClose if !quoted => Part::Pattern(']'),
+        SimpleWord::Star => Part::Literal(String::from("*")),
+        SimpleWord::Question => Part::Literal(String::from("?")),
+        SimpleWord::SquareOpen => Part::Literal(String::from("[")),
+        SimpleWord::SquareClose => Part::Literal(String::from("]")),
+        SimpleWord::Param(parameter) => match expand::positional_fields(parameter) {
+            Some(fields) if !quoted => Part::ExpandedFields(fields),
+            Some(fields) if *parameter == Parameter::At => Part::QuotedFields(fields),
+            // A quoted "$*" joins the parameters with the first character of IFS
+            Some(fields) => {
+                let separator = ifs().chars().next().map(String::from);
+                Part::Literal(fields.join(&separator.unwrap_or_default()))
+            }
+            None => expanded(expand::value(parameter)?),
+        },
+        SimpleWord::Subst(substitution) => expanded(expand::substitution(substitution)?),
+    })
+}
+
+/// A field being built, with its value and the pattern used for globbing
+#[derive(Debug, Default)]
+struct Field {
+    text: String,
+    pattern: String,
+    /// The field contains unquoted pattern characters
+    magic: bool,
+    /// The field exists even if it is empty
+    started: bool,
+}
+
+/// Joins the parts of a word into fields, splitting expanded text on `IFS`.
+/// Whitespace separators are merged and trimmed, while every other separator
+/// ends a field, even an empty one.
+struct Splitter<'a> {
+    ifs: &'a str,
+    fields: Vec<Field>,
+    current: Field,
+    /// Whitespace separated the current field from the text that follows
+    pending: bool,
+}
+
+impl<'a> Splitter<'a> {
+    fn new(ifs: &'a str) -> Splitter<'a> {
+        Splitter {
+            ifs,
+            fields: vec![],
+            current: Field::default(),
+            pending: false,
+        }
+    }
+
+    fn push(&mut self, part: Part) {
+        match part {
+            Part::Literal(x) => {
+                self.end_pending();
+                self.current.pattern.push_str(&glob::escape(&x));
+                self.current.text.push_str(&x);
+                self.current.started = true;
+            }
+            Part::Pattern(x) => {
+                self.end_pending();
+                self.current.pattern.push(x);
+                self.current.text.push(x);
+                self.current.magic = true;
+                self.current.started = true;
+            }
+            Part::Expanded(x) => self.split(&x),
+            Part::ExpandedFields(fields) => {
+                for (index, field) in fields.iter().enumerate() {
+                    if index > 0 && self.current.started {
+                        self.pending = true;
+                    }
+                    self.split(field);
+                }
+            }
+            Part::QuotedFields(fields) => {
+                for (index, field) in fields.into_iter().enumerate() {
+                    if index > 0 {
+                        self.end_field();
+                    }
+                    self.push(Part::Literal(field));
+                }
+            }
+        }
+    }
+
+    fn split(&mut self, text: &str) {
+        for c in text.chars() {
+            if !self.ifs.contains(c) {
+                self.end_pending();
+                match c {
+                    '*' | '?' | '[' => {
+                        self.current.pattern.push(c);
+                        self.current.magic = true;
+                    }
+                    ']' => self.current.pattern.push(c),
+                    c => self.current.pattern.push_str(&glob::escape(&c.to_string())),
+                }
+                self.current.text.push(c);
+                self.current.started = true;
+            } else if c.is_whitespace() {
+                self.pending |= self.current.started;
+            } else {
+                self.end_field();
+            }
+        }
+    }
+
+    fn end_pending(&mut self) {
+        if self.pending {
+            self.end_field();
+        }
+    }
+
+    fn end_field(&mut self) {
+        self.fields.push(std::mem::take(&mut self.current));
+        self.pending = false;
+    }
+
+    fn finish(mut self) -> Vec<Field> {
+        if self.current.started {
+            self.fields.push(self.current);
+        }
+        self.fields
+    }
+}
+
+/// Expand a pattern into the matching paths. Unless `nullglob` or `failglob`
+/// is set, a pattern without matches is kept as it is, as are all patterns
+/// with `noglob`.
+fn expand_pattern(field: &Field) -> Result<Vec<String>, ExecuteError> {
+    let options = OPTIONS.lock().unwrap().clone();
+    if options.noglob {
+        return Ok(vec![field.text.clone()]);
+    }
+    let paths = glob::expand(&field.pattern, &options);
+
+    if !paths.is_empty() || options.nullglob {
+        Ok(paths)
+    } else if options.failglob {
+        Err(ExecuteError::NoMatch(field.text.clone()))
+    } else {
+        Ok(vec![field.text.clone()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use conch_parser::{
+        ast::{Command, ListableCommand, PipeableCommand, RedirectOrCmdWord},
+        lexer::Lexer,
+        parse::DefaultParser,
+    };
+
+    /// Parse the argument of `echo <source>`
+    fn parse(source: &str) -> TopLevelWord<String> {
+        let source = format!("echo {}", source);
+        let mut commands = DefaultParser::new(Lexer::new(source.chars())).into_iter();
+        match commands.next().unwrap().unwrap().0 {
+            Command::List(list) => match list.first {
+                ListableCommand::Single(PipeableCommand::Simple(command)) => {
+                    match &command.redirects_or_cmd_words[1] {
+                        RedirectOrCmdWord::CmdWord(word) => word.clone(),
+                        _ => panic!("Expected a word"),
+                    }
+                }
+                _ => panic!("Expected a simple command"),
+            },
+            _ => panic!("Expected a list"),
+        }
+    }
+
+    fn split(parts: Vec<Part>, ifs: &str) -> Vec<String> {
+        let mut splitter = Splitter::new(ifs);
+        for part in parts {
+            splitter.push(part);
+        }
+        splitter.finish().into_iter().map(|x| x.text).collect()
+    }
+
+    fn literal(x: &str) -> Part {
+        Part::Literal(x.to_string())
+    }
+
+    fn expanded(x: &str) -> Part {
+        Part::Expanded(x.to_string())
+    }
+
+    #[test]
+    fn test_split_whitespace() {
+        assert_eq!(split(vec![expanded("  a  b ")], DEFAULT_IFS), ["a", "b"]);
+        assert_eq!(
+            split(
+                vec![literal("x"), expanded("a b"), literal("y")],
+                DEFAULT_IFS
+            ),
+            ["xa", "by"]
+        );
+        assert_eq!(
+            split(
+                vec![literal("x"), expanded(" a "), literal("y")],
+                DEFAULT_IFS
+            ),
+            ["x", "a", "y"]
+        );
+        assert!(split(vec![expanded("   ")], DEFAULT_IFS).is_empty());
+        assert_eq!(split(vec![literal(""), expanded("")], DEFAULT_IFS), [""]);
+        assert_eq!(split(vec![expanded("a b")], ""), ["a b"]);
+    }
+
+    #[test]
+    fn test_split_separators() {
+        assert_eq!(split(vec![expanded("a::b:")], ":"), ["a", "", "b"]);
+        assert_eq!(split(vec![expanded(":a")], ":"), ["", "a"]);
+        assert_eq!(split(vec![expanded("a : b")], " :"), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_split_positional() {
+        let fields = || vec![String::from("a b"), String::new(), String::from("c")];
+        assert_eq!(
+            split(vec![Part::ExpandedFields(fields())], DEFAULT_IFS),
+            ["a", "b", "c"]
+        );
+        assert_eq!(
+            split(
+                vec![literal("x"), Part::QuotedFields(fields()), literal("y")],
+                DEFAULT_IFS
+            ),
+            ["xa b", "", "cy"]
+        );
+        assert!(split(vec![Part::QuotedFields(vec![])], DEFAULT_IFS).is_empty());
+    }
+
+    #[test]
+    fn test_tilde() {
+        let root = user_home_dir_by_user_name("root").unwrap();
+        assert_eq!(string(&parse("~root/x")).unwrap(), format!("{}/x", root));
+        assert_eq!(string(&parse("'~root'")).unwrap(), "~root");
+        assert_eq!(string(&parse("a~root")).unwrap(), "a~root");
+        assert_eq!(
+            string(&parse("~rush_no_such_user")).unwrap(),
+            "~rush_no_such_user"
+        );
+        assert_eq!(string(&parse("~root:~root")).unwrap(), "~root:~root");
+
+        let word = parse("X=~root:~root/y");
+        assert!(is_assignment(&word));
+        assert_eq!(assignment(&word).unwrap(), format!("X={}:{}/y", root, root));
+        assert!(!is_assignment(&parse("1X=~root")));
+    }
+}
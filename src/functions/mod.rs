@@ -0,0 +1,50 @@
+use conch_parser::ast::DefaultCompoundCommand;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+// The parsed function bodies are reference counted with `Rc`, so they can't be
+// shared between threads and live in thread local storage instead of the
+// `lazy_static` globals.
+thread_local! {
+    static FUNCTIONS: RefCell<HashMap<String, Rc<DefaultCompoundCommand>>> =
+        RefCell::new(HashMap::new());
+}
+
+pub fn define(name: &str, body: Rc<DefaultCompoundCommand>) {
+    FUNCTIONS.with(|x| x.borrow_mut().insert(name.to_string(), body));
+}
+
+pub fn get(name: &str) -> Option<Rc<DefaultCompoundCommand>> {
+    FUNCTIONS.with(|x| x.borrow().get(name).cloned())
+}
+
+/// Remove a function, returning true if it was defined
+pub fn remove(name: &str) -> bool {
+    FUNCTIONS.with(|x| x.borrow_mut().remove(name).is_some())
+}
+
+/// Names of all defined functions in alphabetical order
+pub fn names() -> Vec<String> {
+    let mut names = FUNCTIONS.with(|x| x.borrow().keys().cloned().collect::<Vec<_>>());
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use conch_parser::ast::{CompoundCommand, CompoundCommandKind};
+
+    #[test]
+    fn test_define_remove() {
+        let body = CompoundCommand {
+            kind: CompoundCommandKind::Brace(vec![]),
+            io: vec![],
+        };
+        define("rush_test_function", Rc::new(body));
+        assert!(get("rush_test_function").is_some());
+        assert!(names().contains(&"rush_test_function".to_string()));
+        assert!(remove("rush_test_function"));
+        assert!(!remove("rush_test_function"));
+        assert!(get("rush_test_function").is_none());
+    }
+}
@@ -0,0 +1,365 @@
+use crate::options::Options;
+use std::fs;
+
+/// Return true if `text` matches the shell pattern. `*` matches any string,
+/// `?` any single character and `[...]` a bracket expression. A backslash
+/// makes the next character match literally.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    matches_from(&pattern, &text)
+}
+
+fn matches_from(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Position to resume from after the last `*`: (pattern index, text index)
+    let mut backtrack = None;
+
+    while t < text.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match bracket(&pattern[p..], text[t]) {
+                Some((true, length)) => Some(length),
+                Some((false, _)) => None,
+                None if text[t] == '[' => Some(1),
+                None => None,
+            },
+            Some('\\') if p + 1 < pattern.len() => {
+                if pattern[p + 1] == text[t] {
+                    Some(2)
+                } else {
+                    None
+                }
+            }
+            Some(x) if *x == text[t] => Some(1),
+            _ => None,
+        };
+
+        match (step, backtrack) {
+            (Some(length), _) => {
+                p += length;
+                t += 1;
+            }
+            (None, Some((star, position))) => {
+                p = star + 1;
+                t = position + 1;
+                backtrack = Some((star, position + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|x| *x == '*')
+}
+
+/// Match a character against the bracket expression at the start of
+/// `pattern`. Returns whether it matched and the length of the expression, or
+/// None if the bracket is not terminated and should be treated literally.
+fn bracket(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut index = 1;
+    let negate = matches!(pattern.get(index), Some('!') | Some('^'));
+    if negate {
+        index += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let current = *pattern.get(index)?;
+        if current == ']' && !first {
+            index += 1;
+            break;
+        }
+        first = false;
+
+        if current == '[' && pattern.get(index + 1) == Some(&':') {
+            let rest = &pattern[index + 2..];
+            if let Some(end) = rest.windows(2).position(|x| x == [':', ']']) {
+                let class = rest[..end].iter().collect::<String>();
+                matched |= class_matches(&class, c);
+                index += end + 4;
+                continue;
+            }
+        }
+
+        let (low, length) = match current {
+            '\\' => (*pattern.get(index + 1)?, 2),
+            x => (x, 1),
+        };
+        index += length;
+
+        if pattern.get(index) == Some(&'-') && pattern.get(index + 1).is_some_and(|x| *x != ']') {
+            let (high, length) = match pattern[index + 1] {
+                '\\' => (*pattern.get(index + 2)?, 3),
+                x => (x, 2),
+            };
+            index += length;
+            matched |= low <= c && c <= high;
+        } else {
+            matched |= low == c;
+        }
+    }
+
+    Some((matched != negate, index))
+}
+
+fn class_matches(class: &str, c: char) -> bool {
+    match class {
+        "alnum" => c.is_alphanumeric(),
+        "alpha" => c.is_alphabetic(),
+        "blank" => c == ' ' || c == '\t',
+        "cntrl" => c.is_control(),
+        "digit" => c.is_ascii_digit(),
+        "graph" => c.is_ascii_graphic(),
+        "lower" => c.is_lowercase(),
+        "print" => c.is_ascii_graphic() || c == ' ',
+        "punct" => c.is_ascii_punctuation(),
+        "space" => c.is_whitespace(),
+        "upper" => c.is_uppercase(),
+        "xdigit" => c.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
+
+/// Escape every character with a special meaning in patterns
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Remove the backslashes escaping characters in a pattern
+pub fn unescape(pattern: &str) -> String {
+    let mut result = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Return true if the pattern contains an unescaped `*`, `?` or `[`
+pub fn has_magic(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => (),
+        }
+    }
+    false
+}
+
+/// Expand a pattern into the sorted list of paths it matches. Hidden files are
+/// only matched by a component starting with `.`, unless `dotglob` is set, and
+/// with `globstar` a `**` component matches any number of directories.
+pub fn expand(pattern: &str, options: &Options) -> Vec<String> {
+    let mut components = pattern.split('/').peekable();
+    let mut paths = vec![String::new()];
+    if pattern.starts_with('/') {
+        components.next();
+        paths = vec![String::from("/")];
+    }
+
+    while let Some(component) = components.next() {
+        let last = components.peek().is_none();
+        paths = if component.is_empty() {
+            // A trailing or repeated slash only keeps directories
+            paths
+                .into_iter()
+                .filter(|x| is_dir(x))
+                .map(|x| if x.ends_with('/') { x } else { x + "/" })
+                .collect()
+        } else if component == "**" && options.globstar {
+            paths
+                .into_iter()
+                .flat_map(|x| {
+                    let mut found = if last { vec![] } else { vec![x.clone()] };
+                    descendants(&x, last, options.dotglob, &mut found);
+                    found
+                })
+                .collect()
+        } else if has_magic(component) {
+            paths
+                .into_iter()
+                .flat_map(|x| matching_entries(&x, component, options.dotglob))
+                .filter(|x| last || is_dir(x))
+                .collect()
+        } else {
+            let name = unescape(component);
+            paths
+                .into_iter()
+                .map(|x| join(&x, &name))
+                .filter(|x| fs::symlink_metadata(x).is_ok())
+                .collect()
+        };
+    }
+
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+fn join(directory: &str, name: &str) -> String {
+    if directory.is_empty() || directory.ends_with('/') {
+        format!("{}{}", directory, name)
+    } else {
+        format!("{}/{}", directory, name)
+    }
+}
+
+fn is_dir(path: &str) -> bool {
+    fs::metadata(if path.is_empty() { "." } else { path }).is_ok_and(|x| x.is_dir())
+}
+
+/// Names in a directory, skipping the hidden ones unless requested
+fn entries(directory: &str, hidden: bool) -> Vec<String> {
+    let directory = if directory.is_empty() { "." } else { directory };
+    match fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|x| x.ok()?.file_name().into_string().ok())
+            .filter(|x| hidden || !x.starts_with('.'))
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+fn matching_entries(directory: &str, component: &str, dotglob: bool) -> Vec<String> {
+    let hidden = dotglob || component.starts_with('.') || component.starts_with("\\.");
+    entries(directory, hidden)
+        .into_iter()
+        .filter(|x| matches(component, x))
+        .map(|x| join(directory, &x))
+        .collect()
+}
+
+/// Collect every directory below `directory`, and also the other files if
+/// requested. Symbolic links to directories are not followed.
+fn descendants(directory: &str, files: bool, dotglob: bool, found: &mut Vec<String>) {
+    for name in entries(directory, dotglob) {
+        let path = join(directory, &name);
+        let is_dir = fs::symlink_metadata(&path).is_ok_and(|x| x.is_dir());
+        if is_dir || files {
+            found.push(path.clone());
+        }
+        if is_dir {
+            descendants(&path, files, dotglob, found);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_wildcards() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "anything"));
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "main.rc"));
+        assert!(matches("a*b*c", "aXXbYYc"));
+        assert!(!matches("a*b*c", "aXXbYY"));
+        assert!(matches("?", "x"));
+        assert!(!matches("?", ""));
+        assert!(matches("ma??.rs", "main.rs"));
+        assert!(matches("hello", "hello"));
+        assert!(!matches("hello", "hell"));
+    }
+
+    #[test]
+    fn test_matches_brackets() {
+        assert!(matches("[abc]", "b"));
+        assert!(!matches("[abc]", "d"));
+        assert!(matches("[a-c]x", "cx"));
+        assert!(matches("[!a-c]", "d"));
+        assert!(matches("[^a-c]", "d"));
+        assert!(!matches("[!a-c]", "a"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("[[:digit:]][[:upper:]]", "1A"));
+        assert!(!matches("[[:digit:]]", "a"));
+        assert!(matches("[", "["));
+        assert!(matches("[ab", "[ab"));
+    }
+
+    #[test]
+    fn test_matches_escapes() {
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "a"));
+        assert!(matches(&escape("a*b?"), "a*b?"));
+        assert!(!matches(&escape("a*b?"), "aXbY"));
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("a\\*b\\\\"), "a*b\\");
+        assert!(has_magic("*.rs"));
+        assert!(!has_magic("\\*.rs"));
+    }
+
+    #[test]
+    fn test_expand() {
+        let root = std::env::temp_dir().join(format!("rush_test_glob_{}", std::process::id()));
+        fs::create_dir_all(root.join("src/nested")).unwrap();
+        for file in [
+            "a.rs",
+            "b.rs",
+            "c.txt",
+            ".hidden.rs",
+            "src/d.rs",
+            "src/nested/e.rs",
+        ]
+        .iter()
+        {
+            fs::write(root.join(file), "").unwrap();
+        }
+        let root = root.to_str().unwrap();
+        let expand = |pattern: &str, options: &Options| {
+            expand(&format!("{}/{}", root, pattern), options)
+                .into_iter()
+                .map(|x| x[root.len() + 1..].to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let mut options = Options::default();
+        assert_eq!(expand("*.rs", &options), ["a.rs", "b.rs"]);
+        assert_eq!(expand("[ab].*", &options), ["a.rs", "b.rs"]);
+        assert_eq!(expand(".*.rs", &options), [".hidden.rs"]);
+        assert_eq!(expand("*/", &options), ["src/"]);
+        assert_eq!(expand("*/*.rs", &options), ["src/d.rs"]);
+        assert_eq!(expand("**/*.rs", &options), ["src/d.rs"]);
+        assert!(expand("*.none", &options).is_empty());
+
+        options.dotglob = true;
+        assert_eq!(expand("*.rs", &options), [".hidden.rs", "a.rs", "b.rs"]);
+        options.dotglob = false;
+        options.globstar = true;
+        assert_eq!(
+            expand("**/*.rs", &options),
+            ["a.rs", "b.rs", "src/d.rs", "src/nested/e.rs"]
+        );
+        assert_eq!(
+            expand("src/**", &options),
+            ["src/d.rs", "src/nested", "src/nested/e.rs"]
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -2,14 +2,47 @@ use crate::util;
 use lazy_static::lazy_static;
 use std::{
     collections::HashMap,
-    process::{Child, ExitStatus},
-    sync::{Arc, Mutex},
+    process::ExitStatus,
+    sync::{
+        atomic::{AtomicI32, AtomicUsize},
+        Arc, Mutex,
+    },
 };
 
 pub const CONF_FILE_NAME: &str = ".rush";
 
+/// Control flow change requested by a builtin, which the executor applies once
+/// the builtin returns
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flow {
+    Break(usize),
+    Continue(usize),
+    Return(i32),
+}
+
+/// Prefix of the input redirect target a here-string (`<<<`) is rewritten into
+pub const HERE_STRING_MARKER: char = '\u{1}';
+
+/// Exit code of the last executed command
+pub static LAST_STATUS: AtomicI32 = AtomicI32::new(0);
+/// Process id of the shell, which subshells keep reporting as `$$`
+pub static SHELL_PID: AtomicI32 = AtomicI32::new(0);
+/// Process id of the last command started in the background
+pub static LAST_BACKGROUND_PID: AtomicI32 = AtomicI32::new(0);
+
+/// Number of loops enclosing the command currently being executed
+pub static LOOP_DEPTH: AtomicUsize = AtomicUsize::new(0);
+/// Number of files being executed with `source`, where `return` is allowed
+pub static SOURCE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
 lazy_static! {
-    pub static ref CURRENT_CHILD: Arc<Mutex<Option<Child>>> = Arc::new(Mutex::new(None));
+    /// Exit codes of every stage of the most recently executed pipeline
+    pub static ref PIPE_STATUS: Arc<Mutex<Vec<i32>>> = Arc::new(Mutex::new(vec![]));
+    pub static ref FLOW: Arc<Mutex<Option<Flow>>> = Arc::new(Mutex::new(None));
+    /// Name of the shell or script reported as `$0`
+    pub static ref SHELL_NAME: Arc<Mutex<String>> = Arc::new(Mutex::new(String::from("rush")));
+    /// The positional parameters `$1`, `$2`, ...
+    pub static ref POSITIONAL_PARAMETERS: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(vec![]));
     pub static ref UTIL_COMMANDS: HashMap::<&'static str, fn(&[&str]) -> ExitStatus> = {
         let mut map = HashMap::<&'static str, fn(&[&str]) -> ExitStatus>::new();
         map.insert("cd", util::cd::cd);
@@ -18,10 +51,35 @@ lazy_static! {
         map.insert("pwd", util::pwd::pwd);
         map.insert("exit", util::exit::exit);
         map.insert("exec", util::exec::exec);
+        map.insert("break", util::flow::break_loop);
+        map.insert("continue", util::flow::continue_loop);
+        map.insert("return", util::flow::return_function);
+        map.insert("local", util::local::local);
+        map.insert("declare", util::declare::declare);
+        map.insert("unset", util::unset::unset);
+        map.insert("export", util::export::export);
+        map.insert("readonly", util::readonly::readonly);
+        map.insert("jobs", util::jobs::jobs);
+        map.insert("fg", util::jobs::fg);
+        map.insert("bg", util::jobs::bg);
+        map.insert("wait", util::jobs::wait);
+        map.insert("disown", util::jobs::disown);
+        map.insert("trap", util::trap::trap);
+        map.insert("let", util::arithmetic::let_command);
+        map.insert("alias", util::alias::alias);
+        map.insert("unalias", util::alias::unalias);
+        map.insert("complete", util::complete::complete);
+        map.insert("compgen", util::complete::compgen);
+        map.insert("history", util::history::history);
+        map.insert("set", util::set::set);
+        map.insert("source", util::source::source);
+        map.insert(".", util::source::source);
+        map.insert("shopt", util::shopt::shopt);
+        map.insert(":", util::colon::colon);
         map
     };
 }
 
 pub const STDIN: u16 = 0;
 pub const STDOUT: u16 = 1;
-// pub const STDERR: u16 = 2;
+pub const STDERR: u16 = 2;
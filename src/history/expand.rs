@@ -0,0 +1,453 @@
+/// A line after its history references are replaced
+#[derive(Debug, PartialEq)]
+pub struct Expansion {
+    pub line: String,
+    /// Some reference was replaced
+    pub changed: bool,
+    /// The `:p` modifier was used, the line is printed and not run
+    pub print_only: bool,
+}
+
+/// Characters ending the string of a `!string` event
+const EVENT_DELIMITERS: &str = ":;&|()<>\"'`";
+
+/// Replace the history references of a line: an event designator (`!!`,
+/// `!n`, `!-n`, `!string`, `!?string?`, `!#`) optionally followed by a word
+/// designator (`:n`, `^`, `$`, `*`, `x-y`, `x*`, `x-`) and modifiers (`:h`,
+/// `:t`, `:r`, `:e`, `:p`, `:q`, `:s/old/new/`, `:gs/old/new/`). A line
+/// starting with `^old^new` stands for `!!:s^old^new`. References are not
+/// replaced in single quotes, after a backslash, or when `!` is followed by a
+/// blank, `=` or `(`.
+pub fn expand(line: &str) -> Result<Expansion, String> {
+    let events = super::entries()
+        .into_iter()
+        .map(|(number, entry)| (number, entry.command))
+        .collect::<Vec<_>>();
+    expand_with(line, &events)
+}
+
+fn expand_with(line: &str, events: &[(usize, String)]) -> Result<Expansion, String> {
+    let chars = match line.strip_prefix('^') {
+        Some(x) => format!("!!:s^{}", x).chars().collect::<Vec<_>>(),
+        None => line.chars().collect(),
+    };
+    let mut expansion = Expansion {
+        line: String::with_capacity(line.len()),
+        changed: false,
+        print_only: false,
+    };
+    let (mut single_quoted, mut double_quoted) = (false, false);
+    let mut index = 0;
+
+    while let Some(&c) = chars.get(index) {
+        match c {
+            '\\' if !single_quoted => {
+                expansion
+                    .line
+                    .extend(chars.get(index..index + 2).unwrap_or(&chars[index..]));
+                index += 2;
+                continue;
+            }
+            '\'' if !double_quoted => single_quoted = !single_quoted,
+            '"' if !single_quoted => double_quoted = !double_quoted,
+            '!' if !single_quoted => {
+                // `$!` and `${!name}` are parameter expansions
+                let parameter = index > 0 && chars[index - 1] == '$'
+                    || index > 1 && chars[index - 2..index] == ['$', '{'];
+                let literal = parameter
+                    || match chars.get(index + 1) {
+                        None => true,
+                        Some(x) => {
+                            x.is_whitespace() || "=(".contains(*x) || double_quoted && *x == '"'
+                        }
+                    };
+                if !literal {
+                    index += 1;
+                    let text = reference(&chars, &mut index, events, &mut expansion)?;
+                    expansion.line.push_str(&text);
+                    expansion.changed = true;
+                    continue;
+                }
+            }
+            _ => (),
+        }
+        expansion.line.push(c);
+        index += 1;
+    }
+
+    Ok(expansion)
+}
+
+/// Expand the reference starting after the `!` at `index`, moving `index`
+/// past it
+fn reference(
+    chars: &[char],
+    index: &mut usize,
+    events: &[(usize, String)],
+    expansion: &mut Expansion,
+) -> Result<String, String> {
+    let start = *index - 1;
+    let last = || events.last().map(|(_, x)| x.clone());
+    let event = match chars[*index] {
+        '!' => {
+            *index += 1;
+            last()
+        }
+        '#' => {
+            *index += 1;
+            Some(expansion.line.clone())
+        }
+        x if "^$*%:".contains(x) => last(),
+        '?' => {
+            let end = (*index + 1..chars.len())
+                .find(|x| chars[*x] == '?')
+                .unwrap_or(chars.len());
+            let text = chars[*index + 1..end].iter().collect::<String>();
+            *index = (end + 1).min(chars.len());
+            events
+                .iter()
+                .rev()
+                .find(|(_, x)| x.contains(&text))
+                .map(|(_, x)| x.clone())
+        }
+        x if x.is_ascii_digit() || x == '-' => {
+            let end = (*index + 1..chars.len())
+                .find(|x| !chars[*x].is_ascii_digit())
+                .unwrap_or(chars.len());
+            let number = chars[*index..end].iter().collect::<String>();
+            *index = end;
+            match number.parse::<isize>() {
+                Ok(x) if x < 0 => events
+                    .len()
+                    .checked_sub(x.unsigned_abs())
+                    .map(|x| events[x].1.clone()),
+                Ok(x) => events
+                    .iter()
+                    .find(|(number, _)| *number == x as usize)
+                    .map(|(_, x)| x.clone()),
+                Err(_) => None,
+            }
+        }
+        _ => {
+            let end = (*index..chars.len())
+                .find(|x| chars[*x].is_whitespace() || EVENT_DELIMITERS.contains(chars[*x]))
+                .unwrap_or(chars.len());
+            let prefix = chars[*index..end].iter().collect::<String>();
+            *index = end;
+            events
+                .iter()
+                .rev()
+                .find(|(_, x)| x.starts_with(&prefix))
+                .map(|(_, x)| x.clone())
+        }
+    };
+    let mut text = match event {
+        Some(x) => x,
+        None => {
+            let reference = chars[start..*index].iter().collect::<String>();
+            return Err(format!("{}: event not found", reference));
+        }
+    };
+
+    // The colon may be left out before a word designator starting with one
+    // of `^$*%`
+    let designator = match chars.get(*index) {
+        Some(':') => chars
+            .get(*index + 1)
+            .is_some_and(|x| x.is_ascii_digit() || "^$*-%".contains(*x)),
+        Some(x) => "^$*%".contains(*x),
+        None => false,
+    };
+    if designator {
+        let start = *index;
+        if chars[*index] == ':' {
+            *index += 1;
+        }
+        text = select_words(chars, index, &split_words(&text)).ok_or_else(|| {
+            let designator = chars[start..*index].iter().collect::<String>();
+            format!("{}: bad word specifier", designator)
+        })?;
+    }
+
+    while chars.get(*index) == Some(&':') {
+        let start = *index;
+        *index += 1;
+        let global = chars.get(*index) == Some(&'g');
+        if global {
+            *index += 1;
+        }
+        let modifier = chars.get(*index).copied();
+        *index += 1;
+        match modifier {
+            Some('h') if !global => {
+                if let Some((head, _)) = text.rsplit_once('/') {
+                    text = head.to_string();
+                }
+            }
+            Some('t') if !global => {
+                if let Some((_, tail)) = text.rsplit_once('/') {
+                    text = tail.to_string();
+                }
+            }
+            Some('r') if !global => {
+                if let Some((root, suffix)) = text.rsplit_once('.') {
+                    if !suffix.contains('/') {
+                        text = root.to_string();
+                    }
+                }
+            }
+            Some('e') if !global => {
+                text = match text.rsplit_once('.') {
+                    Some((_, suffix)) if !suffix.contains('/') => format!(".{}", suffix),
+                    _ => String::new(),
+                }
+            }
+            Some('p') if !global => expansion.print_only = true,
+            Some('q') if !global => text = format!("'{}'", text.replace('\'', r"'\''")),
+            Some('s') => {
+                let (old, new) = substitution(chars, index);
+                if old.is_empty() || !text.contains(&old) {
+                    let modifier = chars[start..*index].iter().collect::<String>();
+                    return Err(format!("{}: substitution failed", modifier));
+                }
+                let new = new.replace('&', &old);
+                text = if global {
+                    text.replace(&old, &new)
+                } else {
+                    text.replacen(&old, &new, 1)
+                };
+            }
+            _ => {
+                let modifier = chars[start..(*index).min(chars.len())]
+                    .iter()
+                    .collect::<String>();
+                return Err(format!("{}: unrecognized history modifier", modifier));
+            }
+        }
+    }
+
+    Ok(text)
+}
+
+/// The words of an event selected by the designator at `index`, or None if
+/// they are out of range
+fn select_words(chars: &[char], index: &mut usize, words: &[String]) -> Option<String> {
+    let word_number = |index: &mut usize| -> Option<usize> {
+        match chars.get(*index)? {
+            '^' => {
+                *index += 1;
+                Some(1)
+            }
+            '$' => {
+                *index += 1;
+                words.len().checked_sub(1)
+            }
+            x if x.is_ascii_digit() => {
+                let end = (*index..chars.len())
+                    .find(|x| !chars[*x].is_ascii_digit())
+                    .unwrap_or(chars.len());
+                let number = chars[*index..end].iter().collect::<String>();
+                *index = end;
+                number.parse().ok()
+            }
+            _ => None,
+        }
+    };
+
+    let (first, last) = match chars[*index] {
+        '*' => {
+            *index += 1;
+            (1, words.len().checked_sub(1)?)
+        }
+        '-' => {
+            *index += 1;
+            (0, word_number(index)?)
+        }
+        _ => {
+            let first = word_number(index)?;
+            match chars.get(*index) {
+                Some('*') => {
+                    *index += 1;
+                    (first, words.len().checked_sub(1)?)
+                }
+                Some('-') => {
+                    *index += 1;
+                    let last = match word_number(index) {
+                        Some(x) => x,
+                        None => words.len().checked_sub(2)?,
+                    };
+                    (first, last)
+                }
+                _ => (first, first),
+            }
+        }
+    };
+
+    // A range may be empty, as `*` is for a command without arguments
+    if last >= words.len() || first > last + 1 {
+        return None;
+    }
+    Some(words[first..=last].join(" "))
+}
+
+/// The old and new text of `s/old/new/` where the `s` is before `index`. The
+/// delimiter is the character after `s`, and can be escaped with a backslash.
+/// The last delimiter may be left out at the end of the line.
+fn substitution(chars: &[char], index: &mut usize) -> (String, String) {
+    let delimiter = match chars.get(*index) {
+        Some(x) => *x,
+        None => return Default::default(),
+    };
+    *index += 1;
+    let mut part = || {
+        let mut text = String::new();
+        while let Some(&c) = chars.get(*index) {
+            *index += 1;
+            match c {
+                '\\' if chars.get(*index) == Some(&delimiter) => {
+                    text.push(delimiter);
+                    *index += 1;
+                }
+                x if x == delimiter => break,
+                x => text.push(x),
+            }
+        }
+        text
+    };
+    let old = part();
+    (old, part())
+}
+
+/// Split a command into words at blanks and around operators, keeping quoted
+/// text together
+fn split_words(command: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(x), _) if x == c => quote = None,
+            (Some(_), '\\') | (None, '\\') => {
+                word.push(c);
+                if let Some(x) = chars.next() {
+                    word.push(x);
+                }
+                continue;
+            }
+            (Some(_), _) => (),
+            (None, '\'') | (None, '"') | (None, '`') => quote = Some(c),
+            (None, x) if x.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                continue;
+            }
+            (None, x) if "|&;<>()".contains(x) => {
+                if !word.is_empty() && !word.ends_with(|y: char| "|&;<>()".contains(y)) {
+                    words.push(std::mem::take(&mut word));
+                }
+                word.push(x);
+                if !chars.peek().is_some_and(|y| "|&;<>".contains(*y)) {
+                    words.push(std::mem::take(&mut word));
+                }
+                continue;
+            }
+            _ => (),
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_line(line: &str) -> Result<String, String> {
+        let events = [
+            "echo /a/b.c/d.tar.gz x",
+            "ls -l | wc -l",
+            "echo 'one two' three",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(number, x)| (number + 1, x.to_string()))
+        .collect::<Vec<_>>();
+        expand_with(line, &events).map(|x| x.line)
+    }
+
+    #[test]
+    fn test_events() {
+        assert_eq!(expand_line("!!"), Ok("echo 'one two' three".into()));
+        assert_eq!(expand_line("!1 y"), Ok("echo /a/b.c/d.tar.gz x y".into()));
+        assert_eq!(expand_line("!-2;"), Ok("ls -l | wc -l;".into()));
+        assert_eq!(expand_line("!ls"), Ok("ls -l | wc -l".into()));
+        assert_eq!(expand_line("!?tar?"), Ok("echo /a/b.c/d.tar.gz x".into()));
+        assert_eq!(expand_line("a !#"), Ok("a a ".into()));
+        assert_eq!(
+            expand_line("!nothing"),
+            Err("!nothing: event not found".into())
+        );
+        assert_eq!(expand_line("!4"), Err("!4: event not found".into()));
+        assert_eq!(
+            expand_line(r#"echo ! != \!! '!!' "!!""#),
+            Ok(r#"echo ! != \!! '!!' "echo 'one two' three""#.into())
+        );
+        assert_eq!(
+            expand_line("kill $!; !!"),
+            Ok("kill $!; echo 'one two' three".into())
+        );
+        assert_eq!(
+            expand_line("echo ${!x} \"$!\""),
+            Ok("echo ${!x} \"$!\"".into())
+        );
+    }
+
+    #[test]
+    fn test_words() {
+        assert_eq!(expand_line("!$"), Ok("three".into()));
+        assert_eq!(expand_line("!^"), Ok("'one two'".into()));
+        assert_eq!(expand_line("!*"), Ok("'one two' three".into()));
+        assert_eq!(expand_line("!ls:2-"), Ok("| wc".into()));
+        assert_eq!(expand_line("!ls:1*"), Ok("-l | wc -l".into()));
+        assert_eq!(expand_line("!ls:-1"), Ok("ls -l".into()));
+        assert_eq!(expand_line("!-3:0"), Ok("echo".into()));
+        assert_eq!(expand_line("!!:4"), Err(":4: bad word specifier".into()));
+    }
+
+    #[test]
+    fn test_modifiers() {
+        assert_eq!(expand_line("!1:1:h"), Ok("/a/b.c".into()));
+        assert_eq!(expand_line("!1:1:t"), Ok("d.tar.gz".into()));
+        assert_eq!(expand_line("!1:1:r"), Ok("/a/b.c/d.tar".into()));
+        assert_eq!(expand_line("!1:1:e"), Ok(".gz".into()));
+        assert_eq!(expand_line("!1:$:q"), Ok("'x'".into()));
+        assert_eq!(expand_line("!ls:gs/l/L/"), Ok("Ls -L | wc -L".into()));
+        assert_eq!(expand_line("!ls:s/l/[&]"), Ok("[l]s -l | wc -l".into()));
+        assert_eq!(expand_line("^three^3^ 4"), Ok("echo 'one two' 3 4".into()));
+        assert_eq!(
+            expand_line("^four^4"),
+            Err(":s^four^4: substitution failed".into())
+        );
+        assert_eq!(
+            expand_line("!!:z"),
+            Err(":z: unrecognized history modifier".into())
+        );
+
+        let events = [(1, String::from("ls"))];
+        assert_eq!(
+            expand_with("!!:p", &events),
+            Ok(Expansion {
+                line: String::from("ls"),
+                changed: true,
+                print_only: true,
+            })
+        );
+    }
+}
@@ -0,0 +1,325 @@
+pub mod expand;
+
+use crate::config;
+use lazy_static::lazy_static;
+use nix::fcntl::{flock, FlockArg};
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A command entered by the user
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Seconds since the epoch, 0 if unknown
+    pub time: i64,
+    pub command: String,
+}
+
+#[derive(Default)]
+struct History {
+    entries: Vec<Entry>,
+    /// Number of entries dropped from the front, so the others keep their
+    /// numbers
+    base: usize,
+    /// Most entries kept in memory, 0 for no limit
+    size: usize,
+    /// Most entries kept in the file, 0 for no limit
+    file_size: usize,
+    path: Option<PathBuf>,
+    time_format: String,
+    /// Set when the entries change other than by adding one, so the line
+    /// editor reloads them
+    changed: bool,
+}
+
+lazy_static! {
+    static ref HISTORY: Arc<Mutex<History>> = Arc::new(Mutex::new(History::default()));
+}
+
+/// Apply the history section of the config and load the history file
+pub fn init(conf: &config::History) {
+    let path = match conf.path.strip_prefix("~/") {
+        Some(x) => env::var("HOME").ok().map(|home| Path::new(&home).join(x)),
+        None => Some(PathBuf::from(&conf.path)),
+    };
+    let mut history = HISTORY.lock().unwrap();
+    history.size = conf.size as usize;
+    history.file_size = conf.file_size as usize;
+    history.path = path.filter(|x| !x.as_os_str().is_empty());
+    history.time_format = conf.time_format.clone();
+
+    if let Some(path) = &history.path {
+        match read_file(path, history.file_size) {
+            Ok(entries) => history.entries = entries,
+            Err(x) => eprintln!("rush: {}: {}", path.display(), x),
+        }
+    }
+    history.trim();
+    history.changed = true;
+}
+
+/// Add a command to the history and append it to the history file. Blank
+/// commands and repeats of the last one are skipped, in which case false is
+/// returned.
+pub fn add(command: &str) -> bool {
+    let mut history = HISTORY.lock().unwrap();
+    if command.trim().is_empty()
+        || history.entries.last().map(|x| x.command.as_str()) == Some(command)
+    {
+        return false;
+    }
+
+    let entry = Entry {
+        time: now(),
+        command: command.to_string(),
+    };
+    if let Some(path) = &history.path {
+        if let Err(x) = append_file(path, &entry, history.file_size) {
+            eprintln!("rush: {}: {}", path.display(), x);
+        }
+    }
+    history.entries.push(entry);
+    history.trim();
+    true
+}
+
+/// Every entry with its number, starting at 1 for the oldest one
+pub fn entries() -> Vec<(usize, Entry)> {
+    let history = HISTORY.lock().unwrap();
+    history
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| (history.base + index + 1, entry.clone()))
+        .collect()
+}
+
+/// The strftime format of the times shown by `history`, empty to hide them
+pub fn time_format() -> String {
+    HISTORY.lock().unwrap().time_format.clone()
+}
+
+/// The history file, if there is one
+pub fn path() -> Option<PathBuf> {
+    HISTORY.lock().unwrap().path.clone()
+}
+
+/// Remove every entry, the history file is left alone
+pub fn clear() {
+    let mut history = HISTORY.lock().unwrap();
+    history.entries.clear();
+    history.base = 0;
+    history.changed = true;
+}
+
+/// Remove the entry with the given number, returning false if there is none.
+/// The entries after it are numbered again.
+pub fn delete(number: usize) -> bool {
+    let mut history = HISTORY.lock().unwrap();
+    match number.checked_sub(history.base + 1) {
+        Some(index) if index < history.entries.len() => {
+            history.entries.remove(index);
+            history.changed = true;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Replace the content of a file with the entries
+pub fn write(path: &Path) -> io::Result<()> {
+    let history = HISTORY.lock().unwrap();
+    rewrite(&mut open_locked(path)?, &history.entries)
+}
+
+/// Add the entries of a file after the current ones
+pub fn read(path: &Path) -> io::Result<()> {
+    let mut entries = read_entries(&mut open_locked(path)?)?;
+    let mut history = HISTORY.lock().unwrap();
+    history.entries.append(&mut entries);
+    history.trim();
+    history.changed = true;
+    Ok(())
+}
+
+/// Most entries the line editor has to keep
+pub fn max_len() -> usize {
+    match HISTORY.lock().unwrap().size {
+        0 => usize::MAX,
+        x => x,
+    }
+}
+
+/// The commands in the history, if it changed since the last call other than
+/// by `add`
+pub fn take_changes() -> Option<Vec<String>> {
+    let mut history = HISTORY.lock().unwrap();
+    if !history.changed {
+        return None;
+    }
+    history.changed = false;
+    Some(history.entries.iter().map(|x| x.command.clone()).collect())
+}
+
+impl History {
+    fn trim(&mut self) {
+        if self.size > 0 && self.entries.len() > self.size {
+            let excess = self.entries.len() - self.size;
+            self.entries.drain(..excess);
+            self.base += excess;
+        }
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs() as i64)
+}
+
+/// Open the history file locked against other shells writing it
+fn open_locked(path: &Path) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .mode(0o600)
+        .open(path)?;
+    flock(file.as_raw_fd(), FlockArg::LockExclusive).map_err(|_| io::Error::last_os_error())?;
+    Ok(file)
+}
+
+/// Read the entries of the history file, dropping the oldest ones from it if
+/// there are more than `file_size`
+fn read_file(path: &Path, file_size: usize) -> io::Result<Vec<Entry>> {
+    let mut file = open_locked(path)?;
+    let mut entries = read_entries(&mut file)?;
+    if file_size > 0 && entries.len() > file_size {
+        entries.drain(..entries.len() - file_size);
+        rewrite(&mut file, &entries)?;
+    }
+    Ok(entries)
+}
+
+/// Append an entry to the history file, rewriting it without the oldest
+/// entries if it would hold more than `file_size`
+fn append_file(path: &Path, entry: &Entry, file_size: usize) -> io::Result<()> {
+    let mut file = open_locked(path)?;
+    if file_size > 0 {
+        let mut entries = read_entries(&mut file)?;
+        if entries.len() >= file_size {
+            entries.drain(..entries.len() + 1 - file_size);
+            entries.push(entry.clone());
+            return rewrite(&mut file, &entries);
+        }
+    }
+    // A single write, so the entry is not mixed with the ones of others
+    file.write_all(format_entry(entry).as_bytes())
+}
+
+fn rewrite(file: &mut File, entries: &[Entry]) -> io::Result<()> {
+    file.set_len(0)?;
+    let text = entries.iter().map(format_entry).collect::<String>();
+    file.write_all(text.as_bytes())
+}
+
+fn read_entries(file: &mut File) -> io::Result<Vec<Entry>> {
+    let mut text = String::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_string(&mut text)?;
+    Ok(parse(&text))
+}
+
+/// Every entry is a `#` comment with its time followed by the lines of the
+/// command. Lines without a time before them are commands of their own.
+fn format_entry(entry: &Entry) -> String {
+    format!("#{}\n{}\n", entry.time, entry.command)
+}
+
+fn parse(text: &str) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = vec![];
+    // Whether the lines that follow continue the last command
+    let mut continued = false;
+    let mut time = None;
+
+    for line in text.lines() {
+        if let Some(x) = line.strip_prefix('#').and_then(|x| x.parse().ok()) {
+            time = Some(x);
+            continued = false;
+            continue;
+        }
+        match entries.last_mut() {
+            Some(entry) if continued => {
+                entry.command.push('\n');
+                entry.command.push_str(line);
+            }
+            _ => {
+                continued = time.is_some();
+                entries.push(Entry {
+                    time: time.take().unwrap_or(0),
+                    command: line.to_string(),
+                });
+            }
+        }
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, thread};
+
+    #[test]
+    fn test_parse() {
+        let entries = parse("ls\n#100\necho a\n#200\nfor x in 1\ndo echo\ndone\n");
+        let commands = entries
+            .iter()
+            .map(|x| x.command.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(commands, vec!["ls", "echo a", "for x in 1\ndo echo\ndone"]);
+        assert_eq!(entries[0].time, 0);
+        assert_eq!(entries[2].time, 200);
+        assert_eq!(
+            parse(&entries.iter().map(format_entry).collect::<String>()),
+            entries
+        );
+    }
+
+    #[test]
+    fn test_file() {
+        let path = env::temp_dir().join(format!("rush_test_history_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let threads = (0..4)
+            .map(|x| {
+                let path = path.clone();
+                thread::spawn(move || {
+                    for y in 0..10 {
+                        let entry = Entry {
+                            time: 1,
+                            command: format!("echo {}\necho {}", x, y),
+                        };
+                        append_file(&path, &entry, 30).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let entries = read_file(&path, 20).unwrap();
+        assert_eq!(entries.len(), 20);
+        assert!(entries.iter().all(|x| x.command.starts_with("echo ")));
+        assert_eq!(read_file(&path, 0).unwrap(), entries);
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,540 @@
+use crate::{globals, signals};
+use lazy_static::lazy_static;
+use nix::{
+    sys::{
+        signal::{kill, Signal},
+        wait::{waitpid, WaitPidFlag, WaitStatus},
+    },
+    unistd::{getpgrp, getpid, isatty, setpgid, tcsetpgrp, Pid},
+};
+use std::{
+    convert::TryFrom,
+    sync::{
+        atomic::{AtomicBool, AtomicI32, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Job control is enabled when the shell reads commands from a terminal.
+/// Forked subshells turn it off again.
+pub static JOB_CONTROL: AtomicBool = AtomicBool::new(false);
+/// Process group of the shell, which owns the terminal between commands
+static SHELL_PGID: AtomicI32 = AtomicI32::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProcessStatus {
+    Running,
+    Stopped(i32),
+    Exited(i32),
+    /// Killed by a signal, possibly dumping core
+    Signaled(i32, bool),
+}
+
+impl ProcessStatus {
+    /// The exit code reported for the process in `$?`
+    pub fn code(&self) -> i32 {
+        match self {
+            ProcessStatus::Running => 0,
+            ProcessStatus::Exited(x) => *x,
+            ProcessStatus::Stopped(x) | ProcessStatus::Signaled(x, _) => 128 + x,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Running,
+    Stopped,
+    /// Every process finished. Holds the status of the last one.
+    Done(ProcessStatus),
+}
+
+/// A pipeline or background command with the processes running it
+#[derive(Debug)]
+pub struct Job {
+    pub id: usize,
+    pub pgid: Pid,
+    pub processes: Vec<(Pid, ProcessStatus)>,
+    pub command: String,
+    /// The last change of state has been reported to the user
+    notified: bool,
+    /// The shell is blocked waiting for the job, so reaping must leave its
+    /// processes alone
+    waiting: bool,
+}
+
+impl Job {
+    pub fn state(&self) -> State {
+        let statuses = || self.processes.iter().map(|(_, status)| status);
+        if statuses().any(|x| *x == ProcessStatus::Running) {
+            State::Running
+        } else if statuses().any(|x| matches!(x, ProcessStatus::Stopped(_))) {
+            State::Stopped
+        } else {
+            State::Done(
+                self.processes
+                    .last()
+                    .map(|(_, status)| *status)
+                    .unwrap_or(ProcessStatus::Exited(0)),
+            )
+        }
+    }
+
+    /// Exit codes of every process in the job
+    pub fn codes(&self) -> Vec<i32> {
+        self.processes.iter().map(|(_, x)| x.code()).collect()
+    }
+
+    /// Describe the state of the job the way `jobs` lists it
+    pub fn describe(&self) -> String {
+        match self.state() {
+            State::Running => String::from("Running"),
+            State::Stopped => String::from("Stopped"),
+            State::Done(ProcessStatus::Exited(0)) => String::from("Done"),
+            State::Done(ProcessStatus::Exited(x)) => format!("Exit {}", x),
+            State::Done(ProcessStatus::Signaled(x, core)) => signal_description(x, core),
+            State::Done(_) => String::from("Done"),
+        }
+    }
+
+    fn set_running(&mut self) {
+        for (_, status) in self.processes.iter_mut() {
+            if let ProcessStatus::Stopped(_) = status {
+                *status = ProcessStatus::Running;
+            }
+        }
+        self.notified = false;
+    }
+}
+
+fn signal_description(signal: i32, core: bool) -> String {
+    let description = match Signal::try_from(signal) {
+        Ok(Signal::SIGHUP) => String::from("Hangup"),
+        Ok(Signal::SIGINT) => String::from("Interrupt"),
+        Ok(Signal::SIGQUIT) => String::from("Quit"),
+        Ok(Signal::SIGILL) => String::from("Illegal instruction"),
+        Ok(Signal::SIGTRAP) => String::from("Trace/breakpoint trap"),
+        Ok(Signal::SIGABRT) => String::from("Aborted"),
+        Ok(Signal::SIGBUS) => String::from("Bus error"),
+        Ok(Signal::SIGFPE) => String::from("Floating point exception"),
+        Ok(Signal::SIGKILL) => String::from("Killed"),
+        Ok(Signal::SIGUSR1) => String::from("User defined signal 1"),
+        Ok(Signal::SIGUSR2) => String::from("User defined signal 2"),
+        Ok(Signal::SIGSEGV) => String::from("Segmentation fault"),
+        Ok(Signal::SIGPIPE) => String::from("Broken pipe"),
+        Ok(Signal::SIGALRM) => String::from("Alarm clock"),
+        Ok(Signal::SIGTERM) => String::from("Terminated"),
+        Ok(x) => x.as_str().to_string(),
+        Err(_) => format!("Signal {}", signal),
+    };
+    if core {
+        format!("{} (core dumped)", description)
+    } else {
+        description
+    }
+}
+
+/// The job table
+#[derive(Debug, Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+    /// Job ids from the least to the most recently started, stopped or resumed.
+    /// The last one is the current job `%+` and the one before it `%-`.
+    recent: Vec<usize>,
+}
+
+lazy_static! {
+    pub static ref JOBS: Arc<Mutex<Jobs>> = Arc::new(Mutex::new(Jobs::default()));
+}
+
+impl Jobs {
+    pub fn add(&mut self, pgid: Pid, pids: &[Pid], command: &str) -> usize {
+        let id = self.jobs.iter().map(|x| x.id).max().unwrap_or(0) + 1;
+        self.jobs.push(Job {
+            id,
+            pgid,
+            processes: pids.iter().map(|x| (*x, ProcessStatus::Running)).collect(),
+            command: command.to_string(),
+            notified: false,
+            waiting: false,
+        });
+        self.recent.push(id);
+        id
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|x| x.id == id)
+    }
+
+    fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|x| x.id == id)
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        self.recent.retain(|x| *x != id);
+        let index = self.jobs.iter().position(|x| x.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    /// All jobs ordered by their id
+    pub fn all(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    pub fn current(&self) -> Option<usize> {
+        self.recent.last().copied()
+    }
+
+    pub fn previous(&self) -> Option<usize> {
+        self.recent.iter().rev().nth(1).copied()
+    }
+
+    /// Make a job the current one
+    fn touch(&mut self, id: usize) {
+        self.recent.retain(|x| *x != id);
+        self.recent.push(id);
+    }
+
+    /// The `+` or `-` shown next to the current and previous job
+    pub fn marker(&self, id: usize) -> char {
+        if self.current() == Some(id) {
+            '+'
+        } else if self.previous() == Some(id) {
+            '-'
+        } else {
+            ' '
+        }
+    }
+
+    /// Find the job containing a process
+    pub fn find_pid(&self, pid: Pid) -> Option<usize> {
+        self.jobs
+            .iter()
+            .find(|x| x.processes.iter().any(|(x, _)| *x == pid))
+            .map(|x| x.id)
+    }
+
+    /// Resolve a job specification: `%n`, `%+` or `%%` for the current job,
+    /// `%-` for the previous one, `%name` for the job whose command starts
+    /// with name and `%?name` for the one containing it
+    pub fn find(&self, spec: &str) -> Result<usize, String> {
+        let no_such_job = || format!("{}: no such job", spec);
+        let pattern = spec.strip_prefix('%').unwrap_or(spec);
+
+        let matching = |f: &dyn Fn(&Job) -> bool| {
+            let found = self.jobs.iter().filter(|x| f(x)).collect::<Vec<_>>();
+            match found.as_slice() {
+                [job] => Ok(job.id),
+                [] => Err(no_such_job()),
+                _ => Err(format!("{}: ambiguous job spec", spec)),
+            }
+        };
+
+        match pattern {
+            "" | "+" | "%" => self.current().ok_or_else(no_such_job),
+            "-" => self.previous().ok_or_else(no_such_job),
+            x if x.chars().all(|x| x.is_ascii_digit()) => x
+                .parse()
+                .ok()
+                .filter(|x| self.get(*x).is_some())
+                .ok_or_else(no_such_job),
+            x => match x.strip_prefix('?') {
+                Some(x) => matching(&|job| job.command.contains(x)),
+                None => matching(&|job| job.command.starts_with(x)),
+            },
+        }
+    }
+
+    fn update(&mut self, pid: Pid, status: ProcessStatus) {
+        let id = match self.find_pid(pid) {
+            Some(x) => x,
+            None => return,
+        };
+
+        if let Some(job) = self.get_mut(id) {
+            for process in job.processes.iter_mut().filter(|(x, _)| *x == pid) {
+                process.1 = status;
+            }
+            job.notified = false;
+        }
+        if let ProcessStatus::Stopped(_) = status {
+            self.touch(id);
+        }
+    }
+}
+
+/// Take control of the terminal if the shell is interactive
+pub fn init() {
+    if !isatty(globals::STDIN as i32).unwrap_or(false) {
+        return;
+    }
+
+    signals::ignore_interactive();
+    let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+    let pgid = getpgrp();
+    if tcsetpgrp(globals::STDIN as i32, pgid).is_ok() {
+        SHELL_PGID.store(pgid.as_raw(), Ordering::SeqCst);
+        JOB_CONTROL.store(true, Ordering::SeqCst);
+    }
+}
+
+pub fn enabled() -> bool {
+    JOB_CONTROL.load(Ordering::SeqCst)
+}
+
+/// Move a newly created process into the process group of its job, a new one
+/// if `pgid` is zero. Foreground jobs also get the terminal. Runs in the child
+/// between fork and exec, so it must only make async-signal-safe calls.
+pub fn enter_group(pgid: Pid, foreground: bool) {
+    let _ = setpgid(Pid::from_raw(0), pgid);
+    if foreground {
+        let pgid = getpgrp();
+        let _ = tcsetpgrp(globals::STDIN as i32, pgid);
+    }
+    signals::restore_defaults();
+}
+
+fn give_terminal(pgid: Pid) {
+    if enabled() {
+        let _ = tcsetpgrp(globals::STDIN as i32, pgid);
+    }
+}
+
+fn take_terminal() {
+    give_terminal(Pid::from_raw(SHELL_PGID.load(Ordering::SeqCst)));
+}
+
+fn process_status(status: WaitStatus) -> Option<(Pid, ProcessStatus)> {
+    match status {
+        WaitStatus::Exited(pid, code) => Some((pid, ProcessStatus::Exited(code))),
+        WaitStatus::Signaled(pid, signal, core) => {
+            Some((pid, ProcessStatus::Signaled(signal as i32, core)))
+        }
+        WaitStatus::Stopped(pid, signal) => Some((pid, ProcessStatus::Stopped(signal as i32))),
+        WaitStatus::Continued(pid) => Some((pid, ProcessStatus::Running)),
+        _ => None,
+    }
+}
+
+/// Wait until the processes of a job are no longer running, without holding
+/// the job table locked while blocked
+fn wait_job(id: usize, flags: WaitPidFlag) {
+    loop {
+        let running = JOBS.lock().unwrap().get_mut(id).and_then(|job| {
+            job.waiting = true;
+            job.processes
+                .iter()
+                .find(|(_, status)| *status == ProcessStatus::Running)
+                .map(|(pid, _)| *pid)
+        });
+        let pid = match running {
+            Some(x) => x,
+            None => break,
+        };
+
+        match waitpid(pid, Some(flags)).ok().and_then(process_status) {
+            Some((pid, status)) => JOBS.lock().unwrap().update(pid, status),
+            // The process can't be waited for, so stop expecting it
+            None => JOBS.lock().unwrap().update(pid, ProcessStatus::Exited(127)),
+        }
+    }
+
+    if let Some(job) = JOBS.lock().unwrap().get_mut(id) {
+        job.waiting = false;
+    }
+}
+
+/// Run a job in the foreground until it finishes or is stopped, and return
+/// the exit codes of its processes
+pub fn foreground(id: usize) -> Vec<i32> {
+    if let Some(job) = JOBS.lock().unwrap().get(id) {
+        give_terminal(job.pgid);
+    }
+    wait_job(id, WaitPidFlag::WUNTRACED);
+    take_terminal();
+
+    let mut jobs = JOBS.lock().unwrap();
+    let job = match jobs.get(id) {
+        Some(x) => x,
+        None => return vec![],
+    };
+    let codes = job.codes();
+    if job.state() == State::Stopped {
+        eprintln!();
+        eprintln!("{}", format_job(&jobs, job, false));
+        if let Some(job) = jobs.get_mut(id) {
+            job.notified = true;
+        }
+    } else {
+        // Interrupted and broken pipelines are what the user expects, and
+        // subshells leave reporting to the shell
+        let is_shell = getpid().as_raw() == globals::SHELL_PID.load(Ordering::SeqCst);
+        if let State::Done(ProcessStatus::Signaled(signal, core)) = job.state() {
+            if signal == Signal::SIGINT as i32 {
+                signals::interrupt();
+            }
+            if is_shell && signal != Signal::SIGINT as i32 && signal != Signal::SIGPIPE as i32 {
+                eprintln!("{}", signal_description(signal, core));
+            }
+        }
+        jobs.remove(id);
+    }
+    codes
+}
+
+/// Start a job with the given processes and run it in the foreground
+pub fn run_foreground(pgid: Pid, pids: &[Pid], command: &str) -> Vec<i32> {
+    let id = JOBS.lock().unwrap().add(pgid, pids, command);
+    foreground(id)
+}
+
+/// Resume a stopped job by sending it SIGCONT
+pub fn resume(id: usize) -> Result<(), String> {
+    let mut jobs = JOBS.lock().unwrap();
+    jobs.touch(id);
+    let job = jobs
+        .get_mut(id)
+        .ok_or_else(|| format!("%{}: no such job", id))?;
+
+    if let State::Done(_) = job.state() {
+        return Ok(());
+    }
+
+    job.set_running();
+    let target = if enabled() {
+        Pid::from_raw(-job.pgid.as_raw())
+    } else {
+        job.pgid
+    };
+    kill(target, Signal::SIGCONT).map_err(|x| x.to_string())
+}
+
+/// Wait for a job to terminate and remove it from the table, returning the
+/// exit code of its last process
+pub fn wait(id: usize) -> i32 {
+    wait_job(id, WaitPidFlag::empty());
+    let job = JOBS.lock().unwrap().remove(id);
+    job.and_then(|x| x.codes().last().copied()).unwrap_or(127)
+}
+
+/// Collect the state changes of background jobs without blocking. Runs on
+/// SIGCHLD, so the table stays locked until the processes waited for are
+/// updated and a job being waited for concurrently never loses its status.
+pub fn reap() {
+    let mut jobs = JOBS.lock().unwrap();
+    let pids = jobs
+        .all()
+        .iter()
+        .filter(|x| !x.waiting)
+        .flat_map(|x| x.processes.iter())
+        .filter(|(_, status)| {
+            !matches!(
+                status,
+                ProcessStatus::Exited(_) | ProcessStatus::Signaled(..)
+            )
+        })
+        .map(|(pid, _)| *pid)
+        .collect::<Vec<_>>();
+
+    let flags = WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED | WaitPidFlag::WCONTINUED;
+    for pid in pids {
+        if let Some((pid, status)) = waitpid(pid, Some(flags)).ok().and_then(process_status) {
+            jobs.update(pid, status);
+        }
+    }
+}
+
+/// Report jobs that finished or stopped since the last prompt, and forget the
+/// finished ones. Only done by interactive shells.
+pub fn notify() {
+    if !enabled() {
+        return;
+    }
+    reap();
+
+    let mut jobs = JOBS.lock().unwrap();
+    let changed = jobs
+        .all()
+        .iter()
+        .filter(|x| !x.notified && x.state() != State::Running)
+        .map(|x| x.id)
+        .collect::<Vec<_>>();
+
+    for id in changed {
+        if let Some(job) = jobs.get(id) {
+            eprintln!("{}", format_job(&jobs, job, false));
+        }
+        match jobs.get(id).map(|x| x.state()) {
+            Some(State::Done(_)) => {
+                jobs.remove(id);
+            }
+            _ => {
+                if let Some(job) = jobs.get_mut(id) {
+                    job.notified = true;
+                }
+            }
+        }
+    }
+}
+
+/// Format a line of the `jobs` listing, optionally with the process group
+pub fn format_job(jobs: &Jobs, job: &Job, long: bool) -> String {
+    let mut command = job.command.clone();
+    if job.state() == State::Running {
+        command.push_str(" &");
+    }
+
+    let pgid = if long {
+        format!(" {} ", job.pgid)
+    } else {
+        String::from("  ")
+    };
+    format!(
+        "[{}]{}{}{:<24}{}",
+        job.id,
+        jobs.marker(job.id),
+        pgid,
+        job.describe(),
+        command
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let mut jobs = Jobs::default();
+        let first = jobs.add(Pid::from_raw(100), &[Pid::from_raw(100)], "sleep 10");
+        let second = jobs.add(
+            Pid::from_raw(200),
+            &[Pid::from_raw(200), Pid::from_raw(201)],
+            "cat file | grep x",
+        );
+
+        assert_eq!(jobs.find("%1"), Ok(first));
+        assert_eq!(jobs.find("%%"), Ok(second));
+        assert_eq!(jobs.find("%+"), Ok(second));
+        assert_eq!(jobs.find("%-"), Ok(first));
+        assert_eq!(jobs.find("%sl"), Ok(first));
+        assert_eq!(jobs.find("%?grep"), Ok(second));
+        assert!(jobs.find("%3").is_err());
+        assert!(jobs.find("%x").is_err());
+        assert_eq!(jobs.find_pid(Pid::from_raw(201)), Some(second));
+
+        jobs.update(Pid::from_raw(100), ProcessStatus::Stopped(20));
+        assert_eq!(jobs.current(), Some(first));
+        assert_eq!(jobs.get(first).unwrap().state(), State::Stopped);
+        assert_eq!(jobs.get(first).unwrap().codes(), [148]);
+
+        jobs.update(Pid::from_raw(200), ProcessStatus::Exited(0));
+        assert_eq!(jobs.get(second).unwrap().state(), State::Running);
+        jobs.update(Pid::from_raw(201), ProcessStatus::Signaled(15, false));
+        assert_eq!(jobs.get(second).unwrap().describe(), "Terminated");
+
+        jobs.remove(first);
+        assert_eq!(jobs.current(), Some(second));
+        assert_eq!(jobs.previous(), None);
+    }
+}
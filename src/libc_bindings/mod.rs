@@ -8,6 +8,9 @@ pub fn user_home_dir_by_user_name(name: &str) -> Result<String, String> {
 
     unsafe {
         let passwd_ptr = libc::getpwnam(user.as_ptr());
+        if passwd_ptr.is_null() {
+            return Err(format!("{}: no such user", name));
+        }
         let passwd = *passwd_ptr;
         let name = match CStr::from_ptr(passwd.pw_dir).to_str() {
             Ok(x) => x.to_string(),
@@ -19,3 +22,36 @@ pub fn user_home_dir_by_user_name(name: &str) -> Result<String, String> {
         Ok(name)
     }
 }
+
+extern "C" {
+    fn strftime(
+        s: *mut libc::c_char,
+        max: libc::size_t,
+        format: *const libc::c_char,
+        tm: *const libc::tm,
+    ) -> libc::size_t;
+}
+
+/// Format a time given in seconds since the epoch in the local time zone, as
+/// done by strftime
+pub fn format_time(time: i64, format: &str) -> String {
+    let format = match CString::new(format) {
+        Ok(x) => x,
+        Err(_) => return String::new(),
+    };
+    let mut buffer = [0u8; 256];
+
+    unsafe {
+        let mut tm = std::mem::zeroed::<libc::tm>();
+        if libc::localtime_r(&(time as libc::time_t), &mut tm).is_null() {
+            return String::new();
+        }
+        let length = strftime(
+            buffer.as_mut_ptr() as *mut libc::c_char,
+            buffer.len(),
+            format.as_ptr(),
+            &tm,
+        );
+        String::from_utf8_lossy(&buffer[..length]).into_owned()
+    }
+}
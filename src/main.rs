@@ -1,40 +1,167 @@
+mod aliases;
+mod arithmetic;
+mod cli;
+mod completion;
 mod config;
 mod executer;
+mod functions;
+mod glob;
 mod globals;
+mod history;
+mod jobs;
 mod libc_bindings;
+mod options;
 mod prompt;
 mod signals;
 mod util;
+mod variables;
 use crate::{
+    cli::{Args, Source},
+    completion::ShellHelper,
     config::Config,
-    executer::{execute, ExecuteError},
+    executer::execute_input,
+    options::OPTIONS,
+    prompt::{Prompt, ScriptReader, Stdin},
+    variables::VARIABLES,
 };
-use rustyline::Editor;
-use std::process;
+use nix::unistd::isatty;
+use rustyline::{CompletionType, Editor};
+use std::{
+    env,
+    fs::File,
+    io::{BufReader, Cursor, ErrorKind},
+    path::Path,
+    process,
+    sync::atomic::Ordering,
+};
+
+/// Startup file run by login shells
+const PROFILE_FILE_NAME: &str = ".rush_profile";
+/// Startup file run by interactive shells, unless `ENV` names another one
+const RC_FILE_NAME: &str = ".rushrc";
 
 fn main() {
+    let args = match Args::parse(&env::args().collect::<Vec<_>>()) {
+        Ok(x) => x,
+        Err(x) => usage_error(&x),
+    };
+
     signals::init();
+    globals::SHELL_PID.store(process::id() as i32, Ordering::SeqCst);
 
-    let mut conf = Config::default();
-    conf.load();
+    for (flag, value) in &args.flags {
+        if let Err(x) = OPTIONS.lock().unwrap().set_flag(*flag, *value) {
+            usage_error(&x);
+        }
+    }
+    *globals::SHELL_NAME.lock().unwrap() = args.name.clone();
+    *globals::POSITIONAL_PARAMETERS.lock().unwrap() = args.parameters.clone();
 
-    let mut rl = Editor::<()>::new();
+    let interactive = args.interactive
+        || (args.source == Source::Stdin
+            && isatty(globals::STDIN as i32).unwrap_or(false)
+            && isatty(globals::STDERR as i32).unwrap_or(false));
+    {
+        let mut options = OPTIONS.lock().unwrap();
+        options.interactive = interactive;
+        options.expand_aliases = interactive;
+        options.histexpand = interactive;
+    }
+    if interactive {
+        jobs::init();
+    }
 
-    loop {
-        match conf.prompt.next(&mut rl) {
-            prompt::PromptResult::Commands(x) => {
-                let status = execute(x);
-                if let Err(x) = status {
-                    if let ExecuteError::Empty = x {
-                        // pass
-                    } else {
-                        eprintln!("Prompt error: {:?}", x)
+    let mut conf = Config::default();
+    if interactive {
+        if !args.norc {
+            conf.load();
+        }
+        let mut variables = VARIABLES.lock().unwrap();
+        if variables.get("PS4").is_none() {
+            let _ = variables.set("PS4", conf.prompt.ps4());
+        }
+        for (name, value) in &conf.aliases {
+            aliases::define(name, value);
+        }
+        for (name, spec) in &conf.completions {
+            completion::spec::define(name, spec.clone());
+        }
+        history::init(&conf.history);
+    }
+
+    if !args.norc {
+        let home = env::var("HOME").ok();
+        if args.login {
+            if let Some(home) = &home {
+                run_startup_file(&Path::new(home).join(PROFILE_FILE_NAME));
+            }
+        }
+        if interactive {
+            match env::var("ENV") {
+                Ok(path) if !path.is_empty() => run_startup_file(Path::new(&path)),
+                _ => {
+                    if let Some(home) = &home {
+                        run_startup_file(&Path::new(home).join(RC_FILE_NAME));
                     }
                 }
             }
-            prompt::PromptResult::Error(x) => eprintln!("{}", x),
-            prompt::PromptResult::Eof => process::exit(0),
-            prompt::PromptResult::Interrupt => (),
         }
     }
+
+    match args.source {
+        Source::Command(text) => {
+            let mut reader = ScriptReader::new(Box::new(Cursor::new(text.into_bytes())));
+            execute_input(&Prompt::default(), &mut reader, false);
+        }
+        Source::Script(path) => {
+            let file = match executer::above_user_fds(|| File::open(&path)) {
+                Ok(_) if Path::new(&path).is_dir() => {
+                    eprintln!("rush: {}: Is a directory", path);
+                    process::exit(126);
+                }
+                Ok(x) => x,
+                Err(x) => {
+                    eprintln!("rush: {}: {}", path, executer::error_text(&x));
+                    process::exit(if x.kind() == ErrorKind::NotFound {
+                        127
+                    } else {
+                        126
+                    });
+                }
+            };
+            let mut reader = ScriptReader::new(Box::new(BufReader::new(file)));
+            execute_input(&Prompt::default(), &mut reader, false);
+        }
+        Source::Stdin if interactive => {
+            let config = rustyline::Config::builder()
+                .completion_type(CompletionType::List)
+                .max_history_size(history::max_len())
+                .history_ignore_dups(false)
+                .build();
+            let mut rl = Editor::with_config(config);
+            rl.set_helper(Some(ShellHelper::default()));
+            execute_input(&conf.prompt, &mut rl, true);
+        }
+        Source::Stdin => {
+            let mut reader = ScriptReader::new(Box::new(Stdin));
+            execute_input(&Prompt::default(), &mut reader, false);
+        }
+    }
+
+    executer::exit(globals::LAST_STATUS.load(Ordering::SeqCst));
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("rush: {}", message);
+    eprintln!("{}", cli::USAGE);
+    process::exit(2);
+}
+
+/// Run a startup file in the current shell. A missing file is skipped.
+fn run_startup_file(path: &Path) {
+    match executer::execute_file(path) {
+        Ok(_) => (),
+        Err(x) if x.kind() == ErrorKind::NotFound => (),
+        Err(x) => eprintln!("rush: {}: {}", path.display(), executer::error_text(&x)),
+    }
 }
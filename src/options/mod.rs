@@ -0,0 +1,158 @@
+use lazy_static::lazy_static;
+use std::sync::{Arc, Mutex};
+
+/// Shell options that change how commands are expanded and executed
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// Patterns that match no file expand to nothing
+    pub nullglob: bool,
+    /// Patterns that match no file are an error
+    pub failglob: bool,
+    /// Patterns match hidden files without an explicit leading `.`
+    pub dotglob: bool,
+    /// `**` matches any number of directories
+    pub globstar: bool,
+    /// Aliases are expanded, by default only in interactive shells
+    pub expand_aliases: bool,
+    /// Exit as soon as a command fails, `-e`
+    pub errexit: bool,
+    /// Print commands with their expanded arguments before running them, `-x`
+    pub xtrace: bool,
+    /// Expanding an unset parameter is an error, `-u`
+    pub nounset: bool,
+    /// A pipeline fails with the status of its last failing command
+    pub pipefail: bool,
+    /// `>` does not overwrite existing files, `-C`
+    pub noclobber: bool,
+    /// Patterns are not expanded to paths, `-f`
+    pub noglob: bool,
+    /// Commands are read but not executed, `-n`
+    pub noexec: bool,
+    /// `!` starts a history reference, by default only in interactive
+    /// shells, `-H`
+    pub histexpand: bool,
+    /// Lines with history references are edited again before they run
+    pub histverify: bool,
+    /// The shell reads commands from a user, reported as `i` in `$-`
+    pub interactive: bool,
+}
+
+lazy_static! {
+    pub static ref OPTIONS: Arc<Mutex<Options>> = Arc::new(Mutex::new(Options::default()));
+}
+
+/// Names of the options changed with `shopt`
+pub const SHOPT_NAMES: [&str; 6] = [
+    "dotglob",
+    "expand_aliases",
+    "failglob",
+    "globstar",
+    "histverify",
+    "nullglob",
+];
+
+/// Names of the options changed with `set -o`, and their single letter flags
+pub const SET_OPTIONS: [(&str, Option<char>); 8] = [
+    ("errexit", Some('e')),
+    ("histexpand", Some('H')),
+    ("noclobber", Some('C')),
+    ("noexec", Some('n')),
+    ("noglob", Some('f')),
+    ("nounset", Some('u')),
+    ("pipefail", None),
+    ("xtrace", Some('x')),
+];
+
+impl Options {
+    fn option(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "dotglob" => Some(&mut self.dotglob),
+            "expand_aliases" => Some(&mut self.expand_aliases),
+            "failglob" => Some(&mut self.failglob),
+            "globstar" => Some(&mut self.globstar),
+            "histverify" => Some(&mut self.histverify),
+            "nullglob" => Some(&mut self.nullglob),
+            "errexit" => Some(&mut self.errexit),
+            "histexpand" => Some(&mut self.histexpand),
+            "noclobber" => Some(&mut self.noclobber),
+            "noexec" => Some(&mut self.noexec),
+            "noglob" => Some(&mut self.noglob),
+            "nounset" => Some(&mut self.nounset),
+            "pipefail" => Some(&mut self.pipefail),
+            "xtrace" => Some(&mut self.xtrace),
+            _ => None,
+        }
+    }
+
+    pub fn get(&mut self, name: &str) -> Option<bool> {
+        self.option(name).map(|x| *x)
+    }
+
+    /// Set an option given by its single letter flag
+    pub fn set_flag(&mut self, flag: char, value: bool) -> Result<(), String> {
+        match SET_OPTIONS.iter().find(|(_, x)| *x == Some(flag)) {
+            Some((name, _)) => self.set(name, value),
+            None => Err(format!("-{}: invalid option", flag)),
+        }
+    }
+
+    /// The single letter flags of the enabled options, the value of `$-`
+    pub fn flags(&mut self) -> String {
+        let mut flags = SET_OPTIONS
+            .iter()
+            .filter_map(|(name, flag)| flag.filter(|_| self.get(name) == Some(true)))
+            .collect::<String>();
+        if self.interactive {
+            flags.push('i');
+        }
+        flags
+    }
+
+    pub fn set(&mut self, name: &str, value: bool) -> Result<(), String> {
+        match self.option(name) {
+            Some(x) => {
+                *x = value;
+                Ok(())
+            }
+            None => Err(format!("{}: invalid shell option name", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_get() {
+        let mut options = Options::default();
+        assert_eq!(options.get("nullglob"), Some(false));
+        options.set("nullglob", true).unwrap();
+        assert_eq!(options.get("nullglob"), Some(true));
+        assert!(options.nullglob);
+        assert!(options.set("nothing", true).is_err());
+        assert_eq!(options.get("nothing"), None);
+    }
+
+    #[test]
+    fn test_set_flag() {
+        let mut options = Options::default();
+        options.set_flag('e', true).unwrap();
+        assert!(options.errexit);
+        options.set_flag('e', false).unwrap();
+        assert!(!options.errexit);
+        assert!(options.set_flag('z', true).is_err());
+    }
+
+    #[test]
+    fn test_flags() {
+        let mut options = Options::default();
+        assert_eq!(options.flags(), "");
+        options.set_flag('x', true).unwrap();
+        options.set_flag('C', true).unwrap();
+        options.set("pipefail", true).unwrap();
+        options.interactive = true;
+        assert!(options.noclobber);
+        assert_eq!(options.flags(), "Cxi");
+    }
+}
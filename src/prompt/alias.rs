@@ -0,0 +1,189 @@
+use super::heredoc::{heredoc_delimiters, Quoting};
+use crate::aliases;
+use conch_parser::{lexer::Lexer, token::Token};
+
+/// Reserved words after which the next word is still in command position
+const RESERVED_WORDS: [&str; 10] = [
+    "if", "then", "else", "elif", "while", "until", "do", "time", "!", "{",
+];
+
+/// Replace the aliases used as command names with their values. The value is
+/// expanded again without the alias itself, so aliases referring to each other
+/// can't loop, and a value ending with a blank also expands the next word.
+/// Quoted or escaped words and here-document bodies are left alone.
+pub fn expand_aliases(text: &str) -> String {
+    expand(text, &mut vec![])
+}
+
+fn expand(text: &str, active: &mut Vec<String>) -> String {
+    let tokens = Lexer::new(text.chars()).collect::<Vec<_>>();
+    let mut quoting = Quoting::default();
+    let mut result = String::with_capacity(text.len());
+    // The current line as written, to find the here-documents it starts
+    let mut line = String::new();
+    let mut heredocs: Vec<(String, bool)> = vec![];
+    let (mut command_position, mut assignment) = (true, false);
+    let mut index = 0;
+
+    while index < tokens.len() {
+        let token = &tokens[index];
+
+        if let Some((delimiter, strip_tabs)) = heredocs.first() {
+            if *token == Token::Newline {
+                let body_line = if *strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    &line
+                };
+                if body_line == delimiter {
+                    heredocs.remove(0);
+                }
+                line.clear();
+            } else {
+                line.push_str(token.as_str());
+            }
+            result.push_str(token.as_str());
+            index += 1;
+            continue;
+        }
+
+        if command_position && !quoting.is_quoted() && starts_word(token) {
+            let end = (index..tokens.len())
+                .find(|x| !starts_word(&tokens[*x]))
+                .unwrap_or(tokens.len());
+            let word = tokens[index..end]
+                .iter()
+                .map(Token::as_str)
+                .collect::<String>();
+
+            if tokens[index..end].iter().all(is_plain) {
+                if let Some(value) = aliases::get(&word).filter(|_| !active.contains(&word)) {
+                    active.push(word.clone());
+                    result.push_str(&expand(&value, active));
+                    active.pop();
+                    line.push_str(&word);
+                    command_position = value.ends_with([' ', '\t']);
+                    index = end;
+                    continue;
+                }
+                if RESERVED_WORDS.contains(&word.as_str()) {
+                    result.push_str(&word);
+                    line.push_str(&word);
+                    index = end;
+                    continue;
+                }
+            }
+            // Variable assignments may precede the command name
+            assignment =
+                matches!(token, Token::Name(_)) && tokens.get(index + 1) == Some(&Token::Equals);
+        }
+
+        let unquoted = quoting.unquoted(token);
+        match token {
+            _ if !unquoted => command_position = false,
+            Token::Whitespace(_) if assignment => {
+                command_position = true;
+                assignment = false;
+            }
+            Token::Whitespace(_) => (),
+            x if is_separator(x) => command_position = true,
+            _ => command_position = false,
+        }
+
+        result.push_str(token.as_str());
+        if unquoted && *token == Token::Newline {
+            heredocs.extend(heredoc_delimiters(&line));
+            line.clear();
+        } else {
+            line.push_str(token.as_str());
+        }
+        index += 1;
+    }
+
+    result
+}
+
+/// Tokens ending a command, after which a new one starts
+fn is_separator(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Newline
+            | Token::Semi
+            | Token::DSemi
+            | Token::Amp
+            | Token::Pipe
+            | Token::AndIf
+            | Token::OrIf
+            | Token::ParenOpen
+    )
+}
+
+/// Tokens that are part of a word
+fn starts_word(token: &Token) -> bool {
+    !matches!(
+        token,
+        Token::Whitespace(_)
+            | Token::Less
+            | Token::Great
+            | Token::DLess
+            | Token::DGreat
+            | Token::DLessDash
+            | Token::GreatAnd
+            | Token::LessAnd
+            | Token::Clobber
+            | Token::LessGreat
+            | Token::ParenClose
+    ) && !is_separator(token)
+}
+
+/// Tokens that may appear in the name of an alias
+fn is_plain(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Name(_)
+            | Token::Literal(_)
+            | Token::Dash
+            | Token::Plus
+            | Token::Colon
+            | Token::Percent
+            | Token::Pound
+            | Token::Tilde
+            | Token::Bang
+            | Token::CurlyOpen
+            | Token::CurlyClose
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_aliases() {
+        aliases::define("rush_test_ll", "ls -l");
+        aliases::define("rush_test_sudo", "sudo ");
+        aliases::define("rush_test_loop", "rush_test_loop -a");
+        aliases::define("rush_test_a", "rush_test_b");
+        aliases::define("rush_test_b", "rush_test_a x");
+
+        assert_eq!(expand_aliases("rush_test_ll /"), "ls -l /");
+        assert_eq!(expand_aliases("echo rush_test_ll"), "echo rush_test_ll");
+        assert_eq!(
+            expand_aliases("true && rush_test_ll; x=1 rush_test_ll"),
+            "true && ls -l; x=1 ls -l"
+        );
+        assert_eq!(expand_aliases("rush_test_sudo rush_test_ll"), "sudo  ls -l");
+        assert_eq!(expand_aliases("rush_test_loop"), "rush_test_loop -a");
+        assert_eq!(expand_aliases("rush_test_a"), "rush_test_a x");
+        assert_eq!(expand_aliases("\\rush_test_ll"), "\\rush_test_ll");
+        assert_eq!(expand_aliases("'rush_test_ll'"), "'rush_test_ll'");
+        assert_eq!(
+            expand_aliases("if rush_test_ll; then rush_test_ll; fi"),
+            "if ls -l; then ls -l; fi"
+        );
+        assert_eq!(
+            expand_aliases("cat <<EOF\nrush_test_ll\nEOF\nrush_test_ll"),
+            "cat <<EOF\nrush_test_ll\nEOF\nls -l"
+        );
+    }
+}
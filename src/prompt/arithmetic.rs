@@ -0,0 +1,146 @@
+use super::heredoc::{map_commands, Quoting};
+use crate::arithmetic::parse_number;
+use conch_parser::{lexer::Lexer, token::Token};
+
+/// Rewrite arithmetic the parser can't handle on its own. An `((expr))`
+/// command becomes a call to the `let` builtin, and constants like `16#ff` or
+/// `0x1f` inside `$((...))` are replaced with their decimal value.
+/// Here-document bodies are left alone.
+pub fn rewrite_arithmetic(text: &str) -> String {
+    map_commands(text, rewrite)
+}
+
+fn rewrite(text: &str) -> String {
+    let tokens = Lexer::new(text.chars()).collect::<Vec<_>>();
+    let mut quoting = Quoting::default();
+    let mut result = String::with_capacity(text.len());
+    let mut index = 0;
+
+    while index < tokens.len() {
+        let token = &tokens[index];
+        let expands = quoting.expands();
+        let unquoted = quoting.unquoted(token);
+
+        if expands && *token == Token::Dollar {
+            if let Some(end) = arithmetic_end(&tokens, index + 1) {
+                result.push_str("$((");
+                result.push_str(&decimal_constants(&tokens[index + 3..end - 1]));
+                result.push_str("))");
+                index = end + 1;
+                continue;
+            }
+        }
+
+        if unquoted && (index == 0 || tokens[index - 1] != Token::Dollar) {
+            if let Some(end) = arithmetic_end(&tokens, index) {
+                let expression = tokens[index + 2..end - 1]
+                    .iter()
+                    .map(|x| x.as_str())
+                    .collect::<String>();
+                result.push_str("let \"");
+                result.push_str(&expression.replace('\\', "\\\\").replace('"', "\\\""));
+                result.push('"');
+                index = end + 1;
+                continue;
+            }
+        }
+
+        result.push_str(token.as_str());
+        index += 1;
+    }
+
+    result
+}
+
+/// If an arithmetic expression `((...))` starts at `start`, return the index
+/// of its last closing parenthesis
+pub(super) fn arithmetic_end(tokens: &[Token], start: usize) -> Option<usize> {
+    if tokens.get(start) != Some(&Token::ParenOpen)
+        || tokens.get(start + 1) != Some(&Token::ParenOpen)
+    {
+        return None;
+    }
+
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::ParenOpen => depth += 1,
+            Token::ParenClose => {
+                depth -= 1;
+                // The inner parenthesis must be closed right before the outer
+                // one, otherwise these are two nested subshells
+                if depth == 1 {
+                    return match tokens.get(index + 1) {
+                        Some(Token::ParenClose) => Some(index + 1),
+                        _ => None,
+                    };
+                }
+            }
+            _ => (),
+        }
+    }
+
+    None
+}
+
+fn decimal_constants(tokens: &[Token]) -> String {
+    let mut result = String::new();
+    let mut index = 0;
+
+    while index < tokens.len() {
+        if let Token::Literal(number) = &tokens[index] {
+            if number.starts_with(|x: char| x.is_ascii_digit()) {
+                let mut text = number.clone();
+                let mut length = 1;
+                if let (Some(Token::Pound), Some(Token::Literal(x) | Token::Name(x))) =
+                    (tokens.get(index + 1), tokens.get(index + 2))
+                {
+                    text = format!("{}#{}", number, x);
+                    length = 3;
+                }
+
+                if let Ok(value) = parse_number(&text) {
+                    result.push_str(&value.to_string());
+                    index += length;
+                    continue;
+                }
+            }
+        }
+
+        result.push_str(tokens[index].as_str());
+        index += 1;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite_command() {
+        assert_eq!(rewrite_arithmetic("((x++))"), "let \"x++\"");
+        assert_eq!(
+            rewrite_arithmetic("while (( (i) < 3 )); do :; done"),
+            "while let \" (i) < 3 \"; do :; done"
+        );
+        assert_eq!(rewrite_arithmetic("((a) ; (b))"), "((a) ; (b))");
+        assert_eq!(rewrite_arithmetic("echo '((x))'"), "echo '((x))'");
+        assert_eq!(
+            rewrite_arithmetic("cat <<EOF\n((x))\nEOF\n((x))"),
+            "cat <<EOF\n((x))\nEOF\nlet \"x\""
+        );
+    }
+
+    #[test]
+    fn test_rewrite_constants() {
+        assert_eq!(
+            rewrite_arithmetic("echo $((16#ff + 1))"),
+            "echo $((255 + 1))"
+        );
+        assert_eq!(rewrite_arithmetic("echo \"$((0x10))\""), "echo \"$((16))\"");
+        assert_eq!(rewrite_arithmetic("echo '$((0x10))'"), "echo '$((0x10))'");
+        assert_eq!(rewrite_arithmetic("echo $((x))"), "echo $((x))");
+    }
+}
@@ -0,0 +1,263 @@
+use super::arithmetic::arithmetic_end;
+use crate::globals::HERE_STRING_MARKER;
+use conch_parser::{lexer::Lexer, token::Token};
+
+/// Tracks which tokens of a line are quoted so that redirection operators
+/// inside strings are left alone
+#[derive(Default)]
+pub(super) struct Quoting {
+    single: bool,
+    double: bool,
+    escaped: bool,
+}
+
+impl Quoting {
+    /// Return true if parameter expansions are recognised at the next token
+    pub(super) fn expands(&self) -> bool {
+        !self.single && !self.escaped
+    }
+
+    /// Return true if the next token is inside quotes or escaped
+    pub(super) fn is_quoted(&self) -> bool {
+        self.single || self.double || self.escaped
+    }
+
+    /// Update the state with the next token and report whether it is unquoted
+    pub(super) fn unquoted(&mut self, token: &Token) -> bool {
+        if self.escaped {
+            self.escaped = false;
+            return false;
+        }
+
+        match token {
+            Token::SingleQuote if !self.double => {
+                self.single = !self.single;
+                false
+            }
+            Token::DoubleQuote if !self.single => {
+                self.double = !self.double;
+                false
+            }
+            Token::Backslash if !self.single => {
+                self.escaped = true;
+                false
+            }
+            _ => !self.single && !self.double,
+        }
+    }
+}
+
+/// Rewrite bash-style here-strings (`cmd <<< word`) into an input redirect
+/// whose target starts with [`HERE_STRING_MARKER`]. The parser has no notion
+/// of here-strings, so the executor recognises the marker instead.
+pub fn rewrite_here_strings(text: &str) -> String {
+    map_commands(text, |text| {
+        let tokens = Lexer::new(text.chars()).collect::<Vec<_>>();
+        let mut quoting = Quoting::default();
+        let mut result = String::with_capacity(text.len());
+        let mut index = 0;
+
+        while index < tokens.len() {
+            let token = &tokens[index];
+            if quoting.unquoted(token)
+                && *token == Token::DLess
+                && tokens.get(index + 1) == Some(&Token::Less)
+            {
+                result.push('<');
+                result.push(HERE_STRING_MARKER);
+                index += 2;
+                while let Some(Token::Whitespace(_)) = tokens.get(index) {
+                    index += 1;
+                }
+                continue;
+            }
+
+            result.push_str(token.as_str());
+            index += 1;
+        }
+
+        result
+    })
+}
+
+/// Apply a rewrite to the commands of a text, leaving the bodies of its
+/// here-documents alone
+pub(super) fn map_commands(text: &str, rewrite: impl Fn(&str) -> String) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut commands = String::new();
+    let mut pending: Vec<(String, bool)> = vec![];
+
+    for line in text.split_inclusive('\n') {
+        if let Some((delimiter, strip_tabs)) = pending.first() {
+            let body_line = line.strip_suffix('\n').unwrap_or(line);
+            let body_line = if *strip_tabs {
+                body_line.trim_start_matches('\t')
+            } else {
+                body_line
+            };
+            if body_line == delimiter {
+                pending.remove(0);
+            }
+            result.push_str(line);
+        } else {
+            commands.push_str(line);
+            pending.extend(heredoc_delimiters(line));
+            if !pending.is_empty() {
+                result.push_str(&rewrite(&commands));
+                commands.clear();
+            }
+        }
+    }
+    result.push_str(&rewrite(&commands));
+
+    result
+}
+
+/// Return true if the text contains a here-document whose closing delimiter
+/// hasn't been read yet
+pub fn has_pending_heredoc(text: &str) -> bool {
+    let mut pending: Vec<(String, bool)> = vec![];
+
+    for line in text.split('\n') {
+        if let Some((delimiter, strip_tabs)) = pending.first() {
+            let line = if *strip_tabs {
+                line.trim_start_matches('\t')
+            } else {
+                line
+            };
+            if line == delimiter {
+                pending.remove(0);
+            }
+        } else {
+            pending.extend(heredoc_delimiters(line));
+        }
+    }
+
+    !pending.is_empty()
+}
+
+/// Find the delimiters of all here-documents started on a line. The `<<`
+/// shift operator of `$((...))` and `((...))` is skipped.
+pub(super) fn heredoc_delimiters(line: &str) -> Vec<(String, bool)> {
+    let tokens = Lexer::new(line.chars()).collect::<Vec<_>>();
+    let mut quoting = Quoting::default();
+    let mut delimiters = vec![];
+    let mut index = 0;
+
+    while index < tokens.len() {
+        let token = &tokens[index];
+        index += 1;
+        if !quoting.unquoted(token) {
+            continue;
+        }
+
+        let arithmetic = match token {
+            Token::Dollar => arithmetic_end(&tokens, index),
+            Token::ParenOpen => arithmetic_end(&tokens, index - 1),
+            _ => None,
+        };
+        if let Some(end) = arithmetic {
+            index = end + 1;
+            continue;
+        }
+
+        let strip_tabs = match token {
+            Token::DLess => false,
+            Token::DLessDash => true,
+            _ => continue,
+        };
+        if tokens.get(index) == Some(&Token::Less) {
+            continue;
+        }
+        while let Some(Token::Whitespace(_)) = tokens.get(index) {
+            index += 1;
+        }
+
+        let mut delimiter = String::new();
+        let mut delimiter_quoting = Quoting::default();
+        while let Some(token) = tokens.get(index) {
+            let unquoted = delimiter_quoting.unquoted(token);
+            if unquoted && is_delimiter_end(token) {
+                break;
+            }
+            match token {
+                Token::SingleQuote | Token::DoubleQuote | Token::Backslash if !unquoted => {}
+                _ => delimiter.push_str(token.as_str()),
+            }
+            index += 1;
+        }
+
+        if !delimiter.is_empty() {
+            delimiters.push((delimiter, strip_tabs));
+        }
+    }
+
+    delimiters
+}
+
+fn is_delimiter_end(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Whitespace(_)
+            | Token::Newline
+            | Token::Semi
+            | Token::Amp
+            | Token::Pipe
+            | Token::AndIf
+            | Token::OrIf
+            | Token::Less
+            | Token::Great
+            | Token::DLess
+            | Token::DGreat
+            | Token::DLessDash
+            | Token::GreatAnd
+            | Token::LessAnd
+            | Token::Clobber
+            | Token::LessGreat
+            | Token::ParenOpen
+            | Token::ParenClose
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_heredoc() {
+        assert!(!has_pending_heredoc("echo hi"));
+        assert!(has_pending_heredoc("cat <<EOF"));
+        assert!(has_pending_heredoc("cat <<EOF\nhello"));
+        assert!(!has_pending_heredoc("cat <<EOF\nhello\nEOF"));
+        assert!(has_pending_heredoc("cat <<-EOF\n\thello"));
+        assert!(!has_pending_heredoc("cat <<-EOF\n\thello\n\tEOF"));
+        assert!(!has_pending_heredoc("cat <<'EOF'\n$x\nEOF"));
+        assert!(has_pending_heredoc("cat <<\"EOF\" && cat <<END\nEOF\n"));
+        assert!(!has_pending_heredoc("cat <<\"EOF\" && cat <<END\nEOF\nEND"));
+        assert!(!has_pending_heredoc("echo '<<EOF'"));
+        assert!(!has_pending_heredoc("cat <<< word"));
+        assert!(!has_pending_heredoc("echo $((1 << 4))"));
+        assert!(!has_pending_heredoc("echo $((1<<4)) \"$((2 << 1))\""));
+        assert!(!has_pending_heredoc("((x <<= 1))"));
+        assert!(has_pending_heredoc("echo $((1 << 4)) && cat <<EOF"));
+    }
+
+    #[test]
+    fn test_rewrite_here_strings() {
+        let marker = HERE_STRING_MARKER;
+        assert_eq!(rewrite_here_strings("echo hi"), "echo hi");
+        assert_eq!(
+            rewrite_here_strings("cat <<< word"),
+            format!("cat <{}word", marker)
+        );
+        assert_eq!(
+            rewrite_here_strings("cat 3<<<\"a b\""),
+            format!("cat 3<{}\"a b\"", marker)
+        );
+        assert_eq!(rewrite_here_strings("echo '<<< x'"), "echo '<<< x'");
+        assert_eq!(
+            rewrite_here_strings("cat <<EOF\nuse <<< here\nEOF\ncat <<< x"),
+            format!("cat <<EOF\nuse <<< here\nEOF\ncat <{}x", marker)
+        );
+    }
+}
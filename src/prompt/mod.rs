@@ -1,11 +1,24 @@
+use crate::{history::expand::expand, options::OPTIONS, variables::VARIABLES};
 use conch_parser::{
-    ast::TopLevelCommand,
+    ast::{
+        builder::{Builder, DefaultBuilder},
+        TopLevelCommand,
+    },
     lexer::Lexer,
     parse::{DefaultParser, ParseError},
 };
 use rustyline::error::ReadlineError;
+
+mod alias;
+mod arithmetic;
+mod heredoc;
+mod reader;
+pub mod render;
+pub use reader::{LineReader, ScriptReader, Stdin};
 use serde::{Deserialize, Serialize};
 
+type BuilderError = <DefaultBuilder<String> as Builder>::Error;
+
 #[derive(Debug)]
 pub enum PromptResult {
     Commands(Vec<TopLevelCommand<String>>),
@@ -33,7 +46,7 @@ impl Default for Prompt {
             ps1: String::from("$ "),
             ps2: String::from("$ "),
             ps3: String::from("$ "),
-            ps4: String::from("$ "),
+            ps4: String::from("+ "),
             ps_quote: String::from("' $ "),
             ps_dquote: String::from("\" $ "),
             ps_pipe: String::from("| $ "),
@@ -44,34 +57,97 @@ impl Default for Prompt {
 }
 
 impl Prompt {
-    pub fn next(&self, rl: &mut rustyline::Editor<()>) -> PromptResult {
+    /// The prefix of commands traced with `set -x`
+    pub fn ps4(&self) -> &str {
+        &self.ps4
+    }
+
+    /// The prompt in the given variable, or the configured one if it is unset
+    fn render(&self, name: &str, default: &str) -> String {
+        let prompt = VARIABLES.lock().unwrap().get(name);
+        render::render(prompt.as_deref().unwrap_or(default))
+    }
+
+    pub fn next(&self, rl: &mut dyn LineReader) -> PromptResult {
         let mut line = String::new();
+        let mut escaped_newline = false;
+        // An expanded line to edit again before it runs, with `histverify`
+        let mut initial = String::new();
 
         loop {
-            match rl.readline(self.ps1.as_ref()) {
+            let prompt = if !rl.shows_prompt() {
+                String::new()
+            } else if line.is_empty() {
+                self.render("PS1", &self.ps1)
+            } else {
+                self.render("PS2", &self.ps2)
+            };
+            let input = if initial.is_empty() {
+                rl.read_line(&prompt)
+            } else {
+                rl.read_line_with_initial(&prompt, &std::mem::take(&mut initial))
+            };
+            match input {
                 Ok(input) => {
-                    line.push_str(input.as_ref());
-                    if line.len() >= 2 && &line[line.len() - 1..] == "\\" {
-                        line = String::from(&line[..line.len() - 1]);
-                        PromptResult::Error(ParseError::Custom("".to_string()))
-                    } else {
-                        let lexer = Lexer::new(line.chars());
-                        match DefaultParser::new(lexer)
-                            .into_iter()
-                            .collect::<std::result::Result<Vec<TopLevelCommand<String>>, _>>()
-                        {
+                    let mut input = input.trim_end_matches(&['\r', '\n'][..]).to_string();
+                    if rl.expands_history() {
+                        match expand(&input) {
+                            Ok(x) if x.print_only => {
+                                eprintln!("{}", x.line);
+                                rl.add_history(&x.line);
+                                return PromptResult::Commands(vec![]);
+                            }
+                            Ok(x) if x.changed && OPTIONS.lock().unwrap().histverify => {
+                                initial = x.line;
+                                continue;
+                            }
+                            Ok(x) if x.changed => {
+                                eprintln!("{}", x.line);
+                                input = x.line;
+                            }
+                            Ok(_) => (),
+                            Err(x) => {
+                                return PromptResult::Error(ParseError::Custom(format!(
+                                    "rush: {}",
+                                    x
+                                )))
+                            }
+                        }
+                    }
+                    if !line.is_empty() && !escaped_newline {
+                        line.push('\n');
+                    }
+                    line.push_str(&input);
+                    escaped_newline = line.ends_with('\\');
+                    if escaped_newline {
+                        line.pop();
+                    } else if !heredoc::has_pending_heredoc(&line) {
+                        match parse(&line) {
                             Ok(x) => {
-                                rl.add_history_entry(line.clone());
+                                rl.add_history(&line);
                                 return PromptResult::Commands(x);
                             }
-                            Err(x) => PromptResult::Error(ParseError::Custom(x.to_string())),
+                            Err(x) if is_incomplete(&x) => (),
+                            Err(x) => {
+                                rl.add_history(&line);
+                                return PromptResult::Error(ParseError::Custom(x.to_string()));
+                            }
                         }
                     }
                 }
                 Err(ReadlineError::Interrupted) => {
                     return PromptResult::Interrupt;
                 }
+                Err(ReadlineError::Eof) if line.is_empty() => {
+                    return PromptResult::Eof;
+                }
                 Err(ReadlineError::Eof) => {
+                    return PromptResult::Error(ParseError::Custom(
+                        ParseError::<BuilderError>::UnexpectedEOF.to_string(),
+                    ));
+                }
+                Err(ReadlineError::Io(err)) => {
+                    eprintln!("rush: {}", err);
                     return PromptResult::Eof;
                 }
                 Err(err) => {
@@ -82,3 +158,25 @@ impl Prompt {
         }
     }
 }
+
+/// Parse shell source into commands, after expanding aliases and rewriting
+/// the syntax the parser does not understand itself
+pub fn parse(source: &str) -> Result<Vec<TopLevelCommand<String>>, ParseError<BuilderError>> {
+    let source = if OPTIONS.lock().unwrap().expand_aliases {
+        alias::expand_aliases(source)
+    } else {
+        source.to_string()
+    };
+    let source = heredoc::rewrite_here_strings(&source);
+    let source = arithmetic::rewrite_arithmetic(&source);
+    let lexer = Lexer::new(source.chars());
+    DefaultParser::new(lexer).into_iter().collect()
+}
+
+/// Errors caused by input that may still be completed on the next line
+fn is_incomplete<T>(err: &ParseError<T>) -> bool {
+    matches!(
+        err,
+        ParseError::UnexpectedEOF | ParseError::Unmatched(..) | ParseError::IncompleteCmd(..)
+    )
+}
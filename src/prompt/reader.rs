@@ -0,0 +1,130 @@
+use super::render;
+use crate::{completion::ShellHelper, history, options::OPTIONS};
+use nix::unistd::read;
+use rustyline::{error::ReadlineError, Editor};
+use std::io::{self, ErrorKind, Read};
+
+/// A source of input lines for the shell
+pub trait LineReader {
+    /// Read a line, showing the prompt if there is a user to see it
+    fn read_line(&mut self, prompt: &str) -> Result<String, ReadlineError>;
+
+    /// Read a line starting with text the user can edit
+    fn read_line_with_initial(
+        &mut self,
+        prompt: &str,
+        _initial: &str,
+    ) -> Result<String, ReadlineError> {
+        self.read_line(prompt)
+    }
+
+    /// Remember a complete command entered by the user
+    fn add_history(&mut self, _command: &str) {}
+
+    /// Whether history references in the lines are expanded
+    fn expands_history(&self) -> bool {
+        false
+    }
+
+    /// Whether the prompts are shown, so they need to be rendered
+    fn shows_prompt(&self) -> bool {
+        false
+    }
+}
+
+impl LineReader for Editor<ShellHelper> {
+    fn read_line(&mut self, prompt: &str) -> Result<String, ReadlineError> {
+        self.read_line_with_initial(prompt, "")
+    }
+
+    fn read_line_with_initial(
+        &mut self,
+        prompt: &str,
+        initial: &str,
+    ) -> Result<String, ReadlineError> {
+        if let Some(commands) = history::take_changes() {
+            let history = self.history_mut();
+            history.clear();
+            for command in commands {
+                history.add(command);
+            }
+        }
+        // The line editor measures the prompt without its non-printing
+        // sequences, and the helper shows them
+        if let Some(helper) = self.helper_mut() {
+            helper.set_prompt(prompt);
+        }
+        self.readline_with_initial(&render::visible(prompt), (initial, ""))
+    }
+
+    fn add_history(&mut self, command: &str) {
+        if history::add(command) {
+            self.add_history_entry(command);
+        }
+    }
+
+    fn expands_history(&self) -> bool {
+        OPTIONS.lock().unwrap().histexpand
+    }
+
+    fn shows_prompt(&self) -> bool {
+        true
+    }
+}
+
+/// Reads the lines of a script, a `-c` command or a standard input that is
+/// not a terminal, without prompts or line editing
+pub struct ScriptReader {
+    input: Box<dyn Read>,
+}
+
+impl ScriptReader {
+    pub fn new(input: Box<dyn Read>) -> ScriptReader {
+        ScriptReader { input }
+    }
+}
+
+impl LineReader for ScriptReader {
+    fn read_line(&mut self, _prompt: &str) -> Result<String, ReadlineError> {
+        let mut line = vec![];
+        let mut byte = [0];
+        loop {
+            match self.input.read(&mut byte) {
+                Ok(0) if line.is_empty() => return Err(ReadlineError::Eof),
+                Ok(0) => break,
+                Ok(_) if byte[0] == b'\n' => break,
+                Ok(_) => line.push(byte[0]),
+                Err(x) if x.kind() == ErrorKind::Interrupted => (),
+                Err(x) => return Err(ReadlineError::Io(x)),
+            }
+        }
+        Ok(String::from_utf8_lossy(&line).into_owned())
+    }
+}
+
+/// Standard input without buffering. Commands the shell reads from it are
+/// consumed a byte at a time, so that the commands it runs read what follows.
+pub struct Stdin;
+
+impl Read for Stdin {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let length = buffer.len().min(1);
+        read(crate::globals::STDIN as i32, &mut buffer[..length])
+            .map_err(|x| io::Error::from_raw_os_error(x.as_errno().map_or(0, |x| x as i32)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_script_reader() {
+        let mut reader = ScriptReader::new(Box::new(Cursor::new("echo a\n\necho b")));
+        assert_eq!(reader.read_line("$ ").unwrap(), "echo a");
+        assert_eq!(reader.read_line("$ ").unwrap(), "");
+        assert_eq!(reader.read_line("$ ").unwrap(), "echo b");
+        assert!(matches!(reader.read_line("$ "), Err(ReadlineError::Eof)));
+    }
+}
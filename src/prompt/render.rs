@@ -0,0 +1,222 @@
+use super::arithmetic::rewrite_arithmetic;
+use crate::{
+    executer,
+    globals::{LAST_STATUS, SHELL_NAME},
+    history,
+    jobs::JOBS,
+    libc_bindings::format_time,
+};
+use conch_parser::{lexer::Lexer, parse::DefaultParser};
+use nix::unistd::{geteuid, gethostname, User};
+use std::{
+    env,
+    sync::atomic::Ordering,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Start of a sequence that takes no room on the screen, from `\[`
+pub const START_IGNORE: char = '\x01';
+/// End of a sequence that takes no room on the screen, from `\]`
+pub const END_IGNORE: char = '\x02';
+
+/// Replace the backslash escapes of a prompt string, then expand its
+/// parameters, command substitutions and arithmetic as in double quotes.
+/// The sequences between `\[` and `\]` are kept between `START_IGNORE` and
+/// `END_IGNORE`. `$?` is not changed by the expansion.
+pub fn render(prompt: &str) -> String {
+    let status = LAST_STATUS.load(Ordering::SeqCst);
+    let text = format!("\"{}\"", decode(prompt, status));
+    let text = rewrite_arithmetic(&text);
+
+    let result = match DefaultParser::new(Lexer::new(text.chars())).word() {
+        Ok(Some(word)) => executer::expand_word(&word).map_err(|x| x.to_string()),
+        Ok(None) => Ok(String::new()),
+        Err(x) => Err(x.to_string()),
+    };
+    LAST_STATUS.store(status, Ordering::SeqCst);
+
+    result.unwrap_or_else(|x| {
+        eprintln!("rush: prompt: {}", x);
+        prompt.to_string()
+    })
+}
+
+/// The text of a rendered prompt as it appears on the screen, without the
+/// non-printing sequences
+pub fn visible(prompt: &str) -> String {
+    let mut ignored = false;
+    prompt
+        .chars()
+        .filter(|x| match *x {
+            START_IGNORE => {
+                ignored = true;
+                false
+            }
+            END_IGNORE => {
+                ignored = false;
+                false
+            }
+            _ => !ignored,
+        })
+        .collect()
+}
+
+/// The text of a rendered prompt as written to the terminal
+pub fn printed(prompt: &str) -> String {
+    prompt.replace([START_IGNORE, END_IGNORE], "")
+}
+
+/// Replace the backslash escapes. The result is the inside of a double quoted
+/// string, so the text inserted by the escapes is quoted.
+fn decode(prompt: &str, status: i32) -> String {
+    let mut result = String::with_capacity(prompt.len());
+    let mut chars = prompt.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            if c == '"' {
+                result.push('\\');
+            }
+            result.push(c);
+            continue;
+        }
+        let escape = match chars.next() {
+            Some(x) => x,
+            None => {
+                result.push_str("\\\\");
+                break;
+            }
+        };
+        let text = match escape {
+            'a' => String::from("\x07"),
+            'e' => String::from("\x1b"),
+            'n' => String::from("\n"),
+            'r' => String::from("\r"),
+            '\\' => String::from("\\"),
+            '$' => String::from(if geteuid().is_root() { "#" } else { "$" }),
+            'u' => user_name(),
+            'h' => host_name()
+                .split('.')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            'H' => host_name(),
+            'w' => working_directory(),
+            'W' => match working_directory().as_str() {
+                "/" => String::from("/"),
+                x => x.rsplit('/').next().unwrap_or_default().to_string(),
+            },
+            's' => SHELL_NAME
+                .lock()
+                .unwrap()
+                .rsplit('/')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            'v' | 'V' => String::from(env!("CARGO_PKG_VERSION")),
+            'j' => JOBS.lock().unwrap().all().len().to_string(),
+            '?' => status.to_string(),
+            '!' => history::entries()
+                .last()
+                .map_or(1, |(number, _)| number + 1)
+                .to_string(),
+            't' => time("%H:%M:%S"),
+            'T' => time("%I:%M:%S"),
+            '@' => time("%I:%M %p"),
+            'A' => time("%H:%M"),
+            'd' => time("%a %b %d"),
+            'D' if chars.peek() == Some(&'{') => {
+                chars.next();
+                let format = chars.by_ref().take_while(|x| *x != '}').collect::<String>();
+                time(if format.is_empty() { "%X" } else { &format })
+            }
+            '[' => {
+                result.push(START_IGNORE);
+                continue;
+            }
+            ']' => {
+                result.push(END_IGNORE);
+                continue;
+            }
+            x @ '0'..='7' => {
+                let mut code = x.to_digit(8).unwrap_or_default();
+                for _ in 0..2 {
+                    match chars.peek().and_then(|x| x.to_digit(8)) {
+                        Some(digit) => {
+                            code = code * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                std::char::from_u32(code).map_or_else(String::new, String::from)
+            }
+            x => format!("\\{}", x),
+        };
+        for c in text.chars() {
+            if matches!(c, '$' | '`' | '"' | '\\') {
+                result.push('\\');
+            }
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+fn user_name() -> String {
+    match User::from_uid(geteuid()) {
+        Ok(Some(user)) => user.name,
+        _ => env::var("USER").unwrap_or_default(),
+    }
+}
+
+fn host_name() -> String {
+    let mut buffer = [0u8; 256];
+    match gethostname(&mut buffer) {
+        Ok(x) => x.to_string_lossy().into_owned(),
+        Err(_) => String::new(),
+    }
+}
+
+/// The current directory, with the home directory shown as `~`
+fn working_directory() -> String {
+    let directory = match env::current_dir() {
+        Ok(x) => x.to_string_lossy().into_owned(),
+        Err(_) => return String::new(),
+    };
+    match env::var("HOME") {
+        Ok(home) if !home.is_empty() && home != "/" => match directory.strip_prefix(&home) {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("~{}", rest),
+            _ => directory,
+        },
+        _ => directory,
+    }
+}
+
+fn time(format: &str) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs() as i64);
+    format_time(now, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(r"a\\b \? \x", 3), r"a\\b 3 \\x");
+        assert_eq!(decode(r#"say "hi" $USER"#, 0), r#"say \"hi\" $USER"#);
+        assert_eq!(decode(r"\[\e[1m\]\101", 0), "\x01\x1b[1m\x02A");
+        assert_eq!(decode(r"\D{%%}", 0), "%");
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(render(r"\[\e[32m\]$((1 + 2))> "), "\x01\x1b[32m\x023> ");
+        assert_eq!(visible("\x01\x1b[32m\x023$ "), "3$ ");
+        assert_eq!(printed("\x01\x1b[32m\x023$ "), "\x1b[32m3$ ");
+    }
+}
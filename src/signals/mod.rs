@@ -1,15 +1,281 @@
-use crate::globals::CURRENT_CHILD;
-use signal_hook::{consts::SIGINT, iterator::Signals};
-use std::thread;
+use crate::{executer, jobs};
+use lazy_static::lazy_static;
+use nix::sys::signal::{sigaction, signal, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use signal_hook::{consts::SIGCHLD, iterator::Signals};
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
 
-pub fn init() {
-    let mut signals = Signals::new(&[SIGINT]).unwrap();
+/// Signals an interactive shell ignores, so that neither the keyboard nor a
+/// plain `kill` terminates it
+const TERMINATING: [Signal; 2] = [Signal::SIGQUIT, Signal::SIGTERM];
+/// Signals an interactive shell ignores, so that it is never stopped itself
+/// when the user suspends a job or the shell takes the terminal back
+const STOPPING: [Signal; 3] = [Signal::SIGTSTP, Signal::SIGTTIN, Signal::SIGTTOU];
 
-    thread::spawn(move || {
-        for sig in signals.forever() {
-            if sig == SIGINT {
-                let _ = CURRENT_CHILD.lock().unwrap().as_mut().map(|x| x.kill());
+/// Signals caught since the pending traps were last run, one bit per signal
+static PENDING: AtomicU64 = AtomicU64::new(0);
+/// Signals ignored with `trap '' SIG`, which children keep ignoring
+static IGNORED: AtomicU64 = AtomicU64::new(0);
+static INTERACTIVE: AtomicBool = AtomicBool::new(false);
+/// Set when SIGINT without a trap interrupts the current command line, which
+/// stops the loops and command lists that are running
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// A condition a trap can be set for. `EXIT`, `DEBUG`, `ERR` and `RETURN` are
+/// raised by the shell itself, the others are signal numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Trap {
+    Exit,
+    Signal(i32),
+    Debug,
+    Err,
+    Return,
+}
+
+impl Trap {
+    /// Parse a condition given by name, with or without the `SIG` prefix, or
+    /// by number
+    pub fn parse(name: &str) -> Option<Trap> {
+        let name = name.to_ascii_uppercase();
+        match name.as_str() {
+            "0" | "EXIT" => Some(Trap::Exit),
+            "DEBUG" => Some(Trap::Debug),
+            "ERR" => Some(Trap::Err),
+            "RETURN" => Some(Trap::Return),
+            x => {
+                let signal = match x.parse::<i32>() {
+                    Ok(number) => Signal::try_from(number).ok(),
+                    Err(_) if x.starts_with("SIG") => Signal::from_str(x).ok(),
+                    Err(_) => Signal::from_str(&format!("SIG{}", x)).ok(),
+                };
+                signal.map(|x| Trap::Signal(x as i32))
             }
         }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Trap::Exit => String::from("EXIT"),
+            Trap::Debug => String::from("DEBUG"),
+            Trap::Err => String::from("ERR"),
+            Trap::Return => String::from("RETURN"),
+            Trap::Signal(x) => match Signal::try_from(*x) {
+                Ok(signal) => signal.as_str().to_string(),
+                Err(_) => x.to_string(),
+            },
+        }
+    }
+}
+
+lazy_static! {
+    /// Commands of the traps that are set. An empty command ignores the
+    /// condition.
+    static ref TRAPS: Arc<Mutex<BTreeMap<Trap, String>>> = Arc::new(Mutex::new(BTreeMap::new()));
+}
+
+fn bit(signal: Signal) -> u64 {
+    1 << (signal as i32 - 1)
+}
+
+extern "C" fn catch(signal: libc::c_int) {
+    PENDING.fetch_or(1 << (signal - 1), Ordering::SeqCst);
+}
+
+pub fn init() {
+    let mut signals = executer::above_user_fds(|| Signals::new([SIGCHLD])).unwrap();
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            jobs::reap();
+            PENDING.fetch_or(bit(Signal::SIGCHLD), Ordering::SeqCst);
+        }
     });
 }
+
+fn set_handler(signals: &[Signal], handler: SigHandler) {
+    // Interrupted system calls are restarted, traps only run between commands
+    let action = SigAction::new(handler, SaFlags::SA_RESTART, SigSet::empty());
+    for sig in signals {
+        unsafe {
+            let _ = sigaction(*sig, &action);
+        }
+    }
+}
+
+/// Restore the default action of signals, except those ignored by a trap
+fn set_default(signals: &[Signal]) {
+    let ignored = IGNORED.load(Ordering::SeqCst);
+    for sig in signals.iter().filter(|x| ignored & bit(**x) == 0) {
+        unsafe {
+            let _ = signal(*sig, SigHandler::SigDfl);
+        }
+    }
+}
+
+/// Ignore the signals that would terminate or stop an interactive shell, and
+/// catch SIGINT so the keyboard only interrupts the current command
+pub fn ignore_interactive() {
+    INTERACTIVE.store(true, Ordering::SeqCst);
+    set_handler(&[Signal::SIGINT], SigHandler::Handler(catch));
+    set_handler(&TERMINATING, SigHandler::SigIgn);
+    set_handler(&STOPPING, SigHandler::SigIgn);
+}
+
+/// Restore the default action of the signals that terminate a process, in a
+/// forked subshell
+pub fn restore_terminating() {
+    set_default(&[Signal::SIGINT]);
+    set_default(&TERMINATING);
+}
+
+/// Restore the default action of every signal the interactive shell handles
+/// or ignores, in a process starting a job. Only makes async-signal-safe calls,
+/// so it can run between fork and exec.
+pub fn restore_defaults() {
+    restore_terminating();
+    set_default(&STOPPING);
+}
+
+/// The action the shell takes for a signal without a trap
+fn untrapped_handler(signal: Signal) -> SigHandler {
+    if !INTERACTIVE.load(Ordering::SeqCst) {
+        SigHandler::SigDfl
+    } else if signal == Signal::SIGINT {
+        SigHandler::Handler(catch)
+    } else if TERMINATING.contains(&signal) || STOPPING.contains(&signal) {
+        SigHandler::SigIgn
+    } else {
+        SigHandler::SigDfl
+    }
+}
+
+/// Set the command of a trap, or reset the condition to its default with
+/// `None`. An empty command ignores it.
+pub fn set_trap(trap: Trap, command: Option<&str>) {
+    let mut traps = TRAPS.lock().unwrap();
+    match command {
+        Some(command) => traps.insert(trap, command.to_string()),
+        None => traps.remove(&trap),
+    };
+
+    let signal = match trap {
+        Trap::Signal(x) => match Signal::try_from(x) {
+            Ok(x) => x,
+            Err(_) => return,
+        },
+        _ => return,
+    };
+
+    match command {
+        Some("") => IGNORED.fetch_or(bit(signal), Ordering::SeqCst),
+        _ => IGNORED.fetch_and(!bit(signal), Ordering::SeqCst),
+    };
+    // Reaping children relies on SIGCHLD, which always stays caught
+    if signal == Signal::SIGCHLD {
+        return;
+    }
+    let handler = match command {
+        Some("") => SigHandler::SigIgn,
+        Some(_) => SigHandler::Handler(catch),
+        None => untrapped_handler(signal),
+    };
+    set_handler(&[signal], handler);
+}
+
+/// The command of a trap, if one is set
+pub fn trap(trap: Trap) -> Option<String> {
+    TRAPS.lock().unwrap().get(&trap).cloned()
+}
+
+/// Every trap that is set, ordered by condition
+pub fn traps() -> Vec<(Trap, String)> {
+    TRAPS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(trap, command)| (*trap, command.clone()))
+        .collect()
+}
+
+/// Reset the traps that are not ignored, as a subshell starts without them
+pub fn reset_traps() {
+    let caught = TRAPS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, command)| !command.is_empty())
+        .map(|(trap, _)| *trap)
+        .collect::<Vec<_>>();
+    for trap in caught {
+        set_trap(trap, None);
+    }
+}
+
+/// Mark the current command line as interrupted by SIGINT, unless a trap
+/// handles it. A non-interactive shell goes on, as it is killed itself by a
+/// SIGINT meant for it.
+pub fn interrupt() {
+    if INTERACTIVE.load(Ordering::SeqCst)
+        && !TRAPS
+            .lock()
+            .unwrap()
+            .contains_key(&Trap::Signal(Signal::SIGINT as i32))
+    {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Whether SIGINT interrupted the current command line
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Forget the interruption before running the next command line
+pub fn clear_interrupted() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+/// Take the signals caught since the last call that have a trap set
+pub fn take_pending() -> Vec<Trap> {
+    let pending = PENDING.swap(0, Ordering::SeqCst);
+    if pending == 0 {
+        return vec![];
+    }
+    if pending & bit(Signal::SIGINT) != 0 {
+        interrupt();
+    }
+
+    let traps = TRAPS.lock().unwrap();
+    traps
+        .keys()
+        .filter(|trap| match trap {
+            Trap::Signal(x) => pending & (1 << (x - 1)) != 0,
+            _ => false,
+        })
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_trap() {
+        assert_eq!(Trap::parse("EXIT"), Some(Trap::Exit));
+        assert_eq!(Trap::parse("0"), Some(Trap::Exit));
+        assert_eq!(Trap::parse("err"), Some(Trap::Err));
+        assert_eq!(Trap::parse("INT"), Some(Trap::Signal(2)));
+        assert_eq!(Trap::parse("SIGUSR1"), Some(Trap::Signal(10)));
+        assert_eq!(Trap::parse("15"), Some(Trap::Signal(15)));
+        assert_eq!(Trap::parse("NOPE"), None);
+        assert_eq!(Trap::Signal(2).name(), "SIGINT");
+    }
+}
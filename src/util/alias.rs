@@ -0,0 +1,87 @@
+use crate::aliases;
+use crate::util::exit_status;
+use crate::variables::split_assignment;
+use std::process::ExitStatus;
+
+/// Define aliases with `name=value`, or print the given aliases or all of
+/// them, in a form that can be read back by the shell
+pub fn alias(args: &[&str]) -> ExitStatus {
+    let args = match args.first() {
+        Some(&"-p") | Some(&"--") => &args[1..],
+        _ => args,
+    };
+    if args.is_empty() {
+        for (name, value) in aliases::all() {
+            print_alias(&name, &value);
+        }
+        return exit_status(0);
+    }
+
+    let mut status = 0;
+    for arg in args {
+        match split_assignment(arg) {
+            (name, Some(value)) if aliases::is_valid_name(name) => aliases::define(name, value),
+            (name, Some(_)) => {
+                eprintln!("alias: `{}': invalid alias name", name);
+                status = 1;
+            }
+            (name, None) => match aliases::get(name) {
+                Some(value) => print_alias(name, &value),
+                None => {
+                    eprintln!("alias: {}: not found", name);
+                    status = 1;
+                }
+            },
+        }
+    }
+
+    exit_status(status)
+}
+
+/// Remove the given aliases, or all of them with `-a`
+pub fn unalias(args: &[&str]) -> ExitStatus {
+    match args {
+        [] => {
+            eprintln!("unalias: usage: unalias [-a] name [name ...]");
+            exit_status(2)
+        }
+        ["-a", ..] => {
+            aliases::clear();
+            exit_status(0)
+        }
+        names => {
+            let mut status = 0;
+            for name in names {
+                if !aliases::remove(name) {
+                    eprintln!("unalias: {}: not found", name);
+                    status = 1;
+                }
+            }
+            exit_status(status)
+        }
+    }
+}
+
+fn print_alias(name: &str, value: &str) {
+    println!("alias {}='{}'", name, value.replace('\'', r"'\''"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alias() {
+        assert!(alias(&["rush_test_gs=git status"]).success());
+        assert_eq!(
+            aliases::get("rush_test_gs"),
+            Some(String::from("git status"))
+        );
+        assert!(alias(&["rush_test_gs"]).success());
+        assert!(!alias(&["rush_test_missing"]).success());
+        assert!(!alias(&["x/y=z"]).success());
+        assert!(unalias(&["rush_test_gs"]).success());
+        assert!(!unalias(&["rush_test_gs"]).success());
+        assert_eq!(unalias(&[]), exit_status(2));
+    }
+}
@@ -0,0 +1,40 @@
+use crate::arithmetic;
+use crate::util::exit_status;
+use std::process::ExitStatus;
+
+/// Evaluate each argument as an arithmetic expression. Succeeds if the value
+/// of the last one is not zero.
+pub fn let_command(args: &[&str]) -> ExitStatus {
+    if args.is_empty() {
+        eprintln!("let: expression expected");
+        return exit_status(1);
+    }
+
+    let mut value = 0;
+    for arg in args {
+        match arithmetic::evaluate(arg) {
+            Ok(x) => value = x,
+            Err(x) => {
+                eprintln!("let: {}", x);
+                return exit_status(1);
+            }
+        }
+    }
+
+    exit_status((value == 0) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variables::VARIABLES;
+
+    #[test]
+    fn test_let() {
+        assert!(let_command(&["RUSH_TEST_LET = 2", "RUSH_TEST_LET *= 3"]).success());
+        assert_eq!(VARIABLES.lock().unwrap().get("RUSH_TEST_LET").unwrap(), "6");
+        assert!(!let_command(&["RUSH_TEST_LET - 6"]).success());
+        assert!(!let_command(&["1 / 0"]).success());
+        assert!(!let_command(&[]).success());
+    }
+}
@@ -1,12 +1,13 @@
 // cd ~username will put you in username's home directory.
 use crate::libc_bindings::user_home_dir_by_user_name;
-use std::{env, os::unix::process::ExitStatusExt, process::ExitStatus};
+use crate::util::exit_status;
+use std::{env, process::ExitStatus};
 
 /// Return directory portion of pathname
 pub fn cd(args: &[&str]) -> ExitStatus {
     if args.len() > 1 {
         eprintln!("Too many arguments");
-        return ExitStatusExt::from_raw(1);
+        return exit_status(1);
     }
 
     let next_dir;
@@ -15,7 +16,7 @@ pub fn cd(args: &[&str]) -> ExitStatus {
             Ok(x) => x,
             Err(_) => {
                 eprintln!("Can't find HOME in ENV");
-                return ExitStatusExt::from_raw(2);
+                return exit_status(2);
             }
         }
     } else if args[0] == "-" {
@@ -23,7 +24,7 @@ pub fn cd(args: &[&str]) -> ExitStatus {
             Ok(x) => x,
             Err(_) => {
                 eprintln!("Can't find OLDPWD in ENV");
-                return ExitStatusExt::from_raw(3);
+                return exit_status(3);
             }
         }
     } else if args[0].starts_with('~') {
@@ -32,7 +33,7 @@ pub fn cd(args: &[&str]) -> ExitStatus {
             Ok(x) => next_dir = x,
             Err(_) => {
                 eprintln!("Couldn't find home dir for user {}", &args[0][1..]);
-                return ExitStatusExt::from_raw(4);
+                return exit_status(4);
             }
         }
     } else {
@@ -44,11 +45,11 @@ pub fn cd(args: &[&str]) -> ExitStatus {
         Ok(_) => {
             env::set_var("OLDPWD", old_pwd);
             env::set_var("PWD", env::current_dir().unwrap_or_default());
-            ExitStatusExt::from_raw(0)
+            exit_status(0)
         }
         Err(x) => {
             eprintln!("{}\n", x);
-            ExitStatusExt::from_raw(5)
+            exit_status(5)
         }
     }
 }
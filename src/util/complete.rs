@@ -0,0 +1,122 @@
+use crate::completion::spec::{self, CompletionSpec};
+use crate::util::exit_status;
+use std::process::ExitStatus;
+
+/// Define how the arguments of commands are completed, remove the definitions
+/// with `-r`, or print them with `-p` or without arguments
+pub fn complete(args: &[&str]) -> ExitStatus {
+    let (spec, flags, index) = match CompletionSpec::parse(args, "pr") {
+        Ok(x) => x,
+        Err(x) => {
+            eprintln!("complete: {}", x);
+            eprintln!(
+                "complete: usage: complete [-pr] [-abcdefjkuv] [-o option] [-A action] \
+                 [-W wordlist] [-F function] [-P prefix] [-S suffix] [-X filterpat] [name ...]"
+            );
+            return exit_status(2);
+        }
+    };
+    let names = &args[index..];
+
+    if flags.contains(&'r') {
+        if names.is_empty() {
+            spec::clear();
+            return exit_status(0);
+        }
+        let mut status = 0;
+        for name in names {
+            if !spec::remove(name) {
+                eprintln!("complete: {}: no completion specification", name);
+                status = 1;
+            }
+        }
+        return exit_status(status);
+    }
+
+    if flags.contains(&'p') || args.is_empty() {
+        if names.is_empty() {
+            for (name, spec) in spec::all() {
+                print_spec(&name, &spec);
+            }
+            return exit_status(0);
+        }
+        let mut status = 0;
+        for name in names {
+            match spec::get(name) {
+                Some(spec) => print_spec(name, &spec),
+                None => {
+                    eprintln!("complete: {}: no completion specification", name);
+                    status = 1;
+                }
+            }
+        }
+        return exit_status(status);
+    }
+
+    if names.is_empty() {
+        eprintln!("complete: a command name is required");
+        return exit_status(2);
+    }
+    for name in names {
+        spec::define(name, spec.clone());
+    }
+    exit_status(0)
+}
+
+fn print_spec(name: &str, spec: &CompletionSpec) {
+    let args = spec.to_args();
+    if args.is_empty() {
+        println!("complete {}", name);
+    } else {
+        println!("complete {} {}", args, name);
+    }
+}
+
+/// Print the completions of a word generated by the given options, one per
+/// line. Fails if there are none.
+pub fn compgen(args: &[&str]) -> ExitStatus {
+    let (spec, _, index) = match CompletionSpec::parse(args, "") {
+        Ok(x) => x,
+        Err(x) => {
+            eprintln!("compgen: {}", x);
+            eprintln!(
+                "compgen: usage: compgen [-abcdefjkuv] [-o option] [-A action] [-W wordlist] \
+                 [-F function] [-P prefix] [-S suffix] [-X filterpat] [word]"
+            );
+            return exit_status(2);
+        }
+    };
+
+    let word = args.get(index).copied().unwrap_or_default();
+    let candidates = spec.generate(word, None);
+    for candidate in &candidates {
+        println!("{}", candidate);
+    }
+    exit_status(if candidates.is_empty() { 1 } else { 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete() {
+        assert!(complete(&["-W", "build test", "rush_test_cargo"]).success());
+        assert_eq!(
+            spec::get("/usr/bin/rush_test_cargo").map(|x| x.words),
+            Some(vec![String::from("build"), String::from("test")])
+        );
+        assert!(complete(&["-p", "rush_test_cargo"]).success());
+        assert!(complete(&["-r", "rush_test_cargo"]).success());
+        assert!(!complete(&["-r", "rush_test_cargo"]).success());
+        assert_eq!(complete(&["-W", "a"]), exit_status(2));
+        assert_eq!(complete(&["-A", "nothing", "x"]), exit_status(2));
+    }
+
+    #[test]
+    fn test_compgen() {
+        assert!(compgen(&["-W", "alpha beta", "al"]).success());
+        assert!(!compgen(&["-W", "alpha beta", "x"]).success());
+        assert!(compgen(&["-b", "sho"]).success());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{
+    executer::unparse,
+    functions,
+    util::exit_status,
+    variables::{quote, split_assignment, Variables, VARIABLES},
+};
+use std::process::ExitStatus;
+
+#[derive(Default)]
+struct Options {
+    definitions: bool,
+    names_only: bool,
+    print: bool,
+    export: Option<bool>,
+    readonly: bool,
+}
+
+/// Set variable values and attributes or, with `-f` and `-F`, display
+/// function definitions and names
+pub fn declare(args: &[&str]) -> ExitStatus {
+    let mut options = Options::default();
+    let mut operands = args;
+
+    while let Some(option) = operands
+        .first()
+        .filter(|x| x.len() > 1 && (x.starts_with('-') || x.starts_with('+')))
+    {
+        let enable = option.starts_with('-');
+        for flag in option.chars().skip(1) {
+            match flag {
+                'f' => options.definitions = true,
+                'F' => options.names_only = true,
+                'p' => options.print = true,
+                'x' => options.export = Some(enable),
+                'r' if enable => options.readonly = true,
+                x => {
+                    eprintln!("declare: {}{}: invalid option", &option[..1], x);
+                    return exit_status(2);
+                }
+            }
+        }
+        operands = &operands[1..];
+    }
+
+    if options.definitions || options.names_only {
+        return print_functions(operands, options.names_only);
+    }
+
+    let mut variables = VARIABLES.lock().unwrap();
+    if operands.is_empty() || options.print {
+        let names = if operands.is_empty() {
+            variables.names()
+        } else {
+            operands.iter().map(|x| x.to_string()).collect()
+        };
+        let mut status = 0;
+        for name in names {
+            match variables.get(&name) {
+                Some(value) => println!(
+                    "declare {} {}={}",
+                    attributes(&variables, &name),
+                    name,
+                    quote(&value)
+                ),
+                None => status = 1,
+            }
+        }
+        return exit_status(status);
+    }
+
+    let mut status = 0;
+    for operand in operands {
+        let (name, value) = split_assignment(operand);
+        let mut result = value.map_or(Ok(()), |x| variables.set(name, x));
+        result = result.and_then(|_| match options.export {
+            Some(true) => variables.export(name),
+            Some(false) => {
+                variables.unexport(name);
+                Ok(())
+            }
+            None => Ok(()),
+        });
+        if options.readonly {
+            result = result.and_then(|_| variables.make_readonly(name));
+        }
+        if let Err(x) = result {
+            eprintln!("declare: {}", x);
+            status = 1;
+        }
+    }
+
+    exit_status(status)
+}
+
+fn attributes(variables: &Variables, name: &str) -> String {
+    let mut attributes = String::from("-");
+    if variables.is_readonly(name) {
+        attributes.push('r');
+    }
+    if variables.is_exported(name) {
+        attributes.push('x');
+    }
+    if attributes.len() == 1 {
+        attributes.push('-');
+    }
+    attributes
+}
+
+fn print_functions(names: &[&str], names_only: bool) -> ExitStatus {
+    let names = if names.is_empty() {
+        functions::names()
+    } else {
+        names.iter().map(|x| x.to_string()).collect()
+    };
+
+    let mut status = 0;
+    for name in names {
+        match functions::get(&name) {
+            Some(_) if names_only => println!("declare -f {}", name),
+            Some(body) => println!("{}", unparse::function(&name, &body)),
+            None => status = 1,
+        }
+    }
+
+    exit_status(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_declare() {
+        assert!(!declare(&["-z"]).success());
+        assert!(!declare(&["-f", "rush_test_missing"]).success());
+        assert!(declare(&["RUSH_TEST_DECLARE=1"]).success());
+        assert_eq!(
+            VARIABLES.lock().unwrap().get("RUSH_TEST_DECLARE").unwrap(),
+            "1"
+        );
+        assert!(env::var("RUSH_TEST_DECLARE").is_err());
+
+        assert!(declare(&["-x", "RUSH_TEST_DECLARE"]).success());
+        assert_eq!(env::var("RUSH_TEST_DECLARE").unwrap(), "1");
+        assert!(declare(&["-r", "RUSH_TEST_DECLARE=2"]).success());
+        assert!(!declare(&["RUSH_TEST_DECLARE=3"]).success());
+    }
+}
@@ -1,4 +1,5 @@
-use std::{os::unix::process::ExitStatusExt, path::Path, process::ExitStatus};
+use crate::util::exit_status;
+use std::{path::Path, process::ExitStatus};
 
 /// Return directory portion of pathname
 pub fn dirname(args: &[&str]) -> ExitStatus {
@@ -7,11 +8,11 @@ pub fn dirname(args: &[&str]) -> ExitStatus {
             Ok(x) => println!("{}", x),
             Err(x) => {
                 eprintln!("{}", x);
-                return ExitStatusExt::from_raw(1);
+                return exit_status(1);
             }
         }
     }
-    ExitStatusExt::from_raw(0)
+    exit_status(0)
 }
 
 fn dirname_one(path: &str) -> Result<String, String> {
@@ -1,17 +1,18 @@
-use std::{
-    os::unix::process::{CommandExt, ExitStatusExt},
-    process::ExitStatus,
+use crate::{
+    executer::{command_error, status_code},
+    util::exit_status,
 };
+use std::{os::unix::process::CommandExt, process::ExitStatus};
 
-/// Exit with an exit code
+/// Replace the shell with a command. Without one there is nothing to do here,
+/// the redirections are made permanent by the executer.
 pub fn exec(args: &[&str]) -> ExitStatus {
     if args.is_empty() {
-        return ExitStatusExt::from_raw(1);
+        return exit_status(0);
     }
 
     let err = std::process::Command::new(args[0]).args(&args[1..]).exec();
-    eprintln!("Exec error: {}", err);
-    std::process::exit(1);
+    std::process::exit(status_code(command_error(args[0], err)));
 }
 
 #[cfg(test)]
@@ -19,7 +20,7 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_exec_err() {
-        assert_eq!(exec(&[]), ExitStatusExt::from_raw(1));
+    fn test_exec_empty() {
+        assert_eq!(exec(&[]), exit_status(0));
     }
 }
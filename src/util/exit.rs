@@ -1,23 +1,25 @@
-use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+use crate::{executer, globals::LAST_STATUS, util::exit_status};
+use std::{process::ExitStatus, sync::atomic::Ordering};
 
-/// Exit with an exit code
+/// Exit with an exit code, the status of the last command by default, after
+/// running the EXIT trap
 pub fn exit(args: &[&str]) -> ExitStatus {
     if args.len() >= 2 {
         eprintln!("Too many arguments");
-        return ExitStatusExt::from_raw(1);
+        return exit_status(1);
     }
 
     if args.len() == 1 {
         match args[0].parse::<i32>() {
-            Ok(exit_code) => std::process::exit(exit_code),
+            Ok(exit_code) => executer::exit(exit_code),
             Err(_) => {
                 eprintln!("Expected argument to be an integer");
-                return ExitStatusExt::from_raw(2);
+                return exit_status(2);
             }
         }
     }
 
-    std::process::exit(0);
+    executer::exit(LAST_STATUS.load(Ordering::SeqCst));
 }
 
 #[cfg(test)]
@@ -26,7 +28,7 @@ mod tests {
 
     #[test]
     fn test_exit_err() {
-        assert_eq!(exit(&["123", "123"]), ExitStatusExt::from_raw(1));
-        assert_eq!(exit(&["wrong args"]), ExitStatusExt::from_raw(2));
+        assert_eq!(exit(&["123", "123"]), exit_status(1));
+        assert_eq!(exit(&["wrong args"]), exit_status(2));
     }
 }
@@ -0,0 +1,71 @@
+use crate::util::exit_status;
+use crate::variables::{quote, split_assignment, VARIABLES};
+use std::process::ExitStatus;
+
+/// Set the export attribute for variables, or remove it with `-n`
+pub fn export(args: &[&str]) -> ExitStatus {
+    let mut remove = false;
+    let mut operands = args;
+
+    while let Some(option) = operands.first().filter(|x| x.starts_with('-')) {
+        match *option {
+            "-n" => remove = true,
+            "-p" => (),
+            "--" => {
+                operands = &operands[1..];
+                break;
+            }
+            x => {
+                eprintln!("export: {}: invalid option", x);
+                return exit_status(2);
+            }
+        }
+        operands = &operands[1..];
+    }
+
+    let mut variables = VARIABLES.lock().unwrap();
+    if operands.is_empty() {
+        for name in variables.names() {
+            if variables.is_exported(&name) {
+                let value = variables.get(&name).unwrap_or_default();
+                println!("export {}={}", name, quote(&value));
+            }
+        }
+        return exit_status(0);
+    }
+
+    let mut status = 0;
+    for operand in operands {
+        let (name, value) = split_assignment(operand);
+        let result = if remove {
+            variables.unexport(name);
+            Ok(())
+        } else {
+            value
+                .map_or(Ok(()), |x| variables.set(name, x))
+                .and_then(|_| variables.export(name))
+        };
+        if let Err(x) = result {
+            eprintln!("export: {}", x);
+            status = 1;
+        }
+    }
+
+    exit_status(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_export() {
+        assert!(export(&["RUSH_TEST_EXPORT=1"]).success());
+        assert_eq!(env::var("RUSH_TEST_EXPORT").unwrap(), "1");
+        assert!(export(&["-n", "RUSH_TEST_EXPORT"]).success());
+        assert!(env::var("RUSH_TEST_EXPORT").is_err());
+        assert!(!export(&["1=2"]).success());
+        assert!(!export(&["-z"]).success());
+    }
+}
@@ -0,0 +1,85 @@
+use crate::{
+    globals::{Flow, FLOW, LAST_STATUS, LOOP_DEPTH, SOURCE_DEPTH},
+    util::exit_status,
+    variables::VARIABLES,
+};
+use std::{process::ExitStatus, sync::atomic::Ordering};
+
+/// Exit from a for, while or until loop
+pub fn break_loop(args: &[&str]) -> ExitStatus {
+    request("break", args, Flow::Break)
+}
+
+/// Resume the next iteration of the enclosing for, while or until loop
+pub fn continue_loop(args: &[&str]) -> ExitStatus {
+    request("continue", args, Flow::Continue)
+}
+
+/// Return from a shell function or a sourced file with the given exit code, or
+/// with the exit code of the last executed command
+pub fn return_function(args: &[&str]) -> ExitStatus {
+    if args.len() > 1 {
+        eprintln!("return: Too many arguments");
+        return exit_status(1);
+    }
+
+    let code = match args.first().map(|x| x.parse::<i32>()) {
+        None => LAST_STATUS.load(Ordering::SeqCst),
+        Some(Ok(x)) => x & 0xff,
+        Some(Err(_)) => {
+            eprintln!("return: {}: numeric argument required", args[0]);
+            return exit_status(2);
+        }
+    };
+
+    if VARIABLES.lock().unwrap().scope_depth() == 0 && SOURCE_DEPTH.load(Ordering::SeqCst) == 0 {
+        eprintln!("return: can only `return' from a function or sourced script");
+        return exit_status(1);
+    }
+
+    *FLOW.lock().unwrap() = Some(Flow::Return(code));
+    exit_status(code)
+}
+
+fn request(name: &str, args: &[&str], flow: fn(usize) -> Flow) -> ExitStatus {
+    if args.len() > 1 {
+        eprintln!("{}: Too many arguments", name);
+        return exit_status(1);
+    }
+
+    let levels = match args.first().map(|x| x.parse::<usize>()) {
+        None => 1,
+        Some(Ok(x)) if x > 0 => x,
+        Some(_) => {
+            eprintln!("{}: {}: loop count out of range", name, args[0]);
+            return exit_status(1);
+        }
+    };
+
+    let depth = LOOP_DEPTH.load(Ordering::SeqCst);
+    if depth == 0 {
+        eprintln!(
+            "{}: only meaningful in a `for', `while', or `until' loop",
+            name
+        );
+        return exit_status(0);
+    }
+
+    *FLOW.lock().unwrap() = Some(flow(levels.min(depth)));
+    exit_status(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loop_control() {
+        assert!(!break_loop(&["0"]).success());
+        assert!(!continue_loop(&["x"]).success());
+        assert!(!break_loop(&["1", "2"]).success());
+        assert!(!return_function(&["1"]).success());
+        assert!(!return_function(&["x"]).success());
+        assert_eq!(*FLOW.lock().unwrap(), None);
+    }
+}
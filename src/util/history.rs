@@ -0,0 +1,132 @@
+use crate::history::{self, expand::expand};
+use crate::libc_bindings::format_time;
+use crate::util::exit_status;
+use std::{path::PathBuf, process::ExitStatus};
+
+const USAGE: &str = "history: usage: history [-c] [-d offset] [n | string] or history -rw [filename] or history -p arg [arg ...]";
+
+/// List the history, the last `n` entries, or the ones containing a string.
+/// `-c` clears it, `-d` deletes an entry, `-w` and `-r` write it to and read
+/// it from a file, and `-p` prints its arguments after history expansion.
+pub fn history(args: &[&str]) -> ExitStatus {
+    match args {
+        ["-c", ..] => {
+            history::clear();
+            exit_status(0)
+        }
+        ["-d"] => {
+            eprintln!("history: -d: option requires an argument");
+            eprintln!("{}", USAGE);
+            exit_status(2)
+        }
+        ["-d", offset, ..] => delete(offset),
+        ["-w", rest @ ..] | ["-r", rest @ ..] => {
+            let path = match rest.first() {
+                Some(x) => PathBuf::from(x),
+                None => match history::path() {
+                    Some(x) => x,
+                    None => {
+                        eprintln!("history: no history file");
+                        return exit_status(1);
+                    }
+                },
+            };
+            let result = if args[0] == "-w" {
+                history::write(&path)
+            } else {
+                history::read(&path)
+            };
+            match result {
+                Ok(_) => exit_status(0),
+                Err(x) => {
+                    eprintln!("history: {}: {}", path.display(), x);
+                    exit_status(1)
+                }
+            }
+        }
+        ["-p", rest @ ..] => {
+            let mut status = 0;
+            for arg in rest {
+                match expand(arg) {
+                    Ok(x) => println!("{}", x.line),
+                    Err(x) => {
+                        eprintln!("history: {}", x);
+                        status = 1;
+                    }
+                }
+            }
+            exit_status(status)
+        }
+        [x, ..] if x.starts_with('-') && *x != "-" && x.parse::<i64>().is_err() => {
+            eprintln!("history: {}: invalid option", x);
+            eprintln!("{}", USAGE);
+            exit_status(2)
+        }
+        [] => list(history::entries()),
+        [x] => match x.parse::<usize>() {
+            Ok(count) => {
+                let entries = history::entries();
+                let skip = entries.len().saturating_sub(count);
+                list(entries.into_iter().skip(skip).collect())
+            }
+            Err(_) => list(
+                history::entries()
+                    .into_iter()
+                    .filter(|(_, entry)| entry.command.contains(x))
+                    .collect(),
+            ),
+        },
+        _ => {
+            eprintln!("history: too many arguments");
+            exit_status(2)
+        }
+    }
+}
+
+/// Delete the entry with the given number, or counted back from the end if it
+/// is negative
+fn delete(offset: &str) -> ExitStatus {
+    let number = match offset.parse::<i64>() {
+        Ok(x) if x < 0 => history::entries()
+            .iter()
+            .rev()
+            .nth((-x - 1) as usize)
+            .map(|(number, _)| *number),
+        Ok(x) => Some(x as usize),
+        Err(_) => None,
+    };
+    if number.is_some_and(history::delete) {
+        exit_status(0)
+    } else {
+        eprintln!("history: {}: history position out of range", offset);
+        exit_status(1)
+    }
+}
+
+fn list(entries: Vec<(usize, history::Entry)>) -> ExitStatus {
+    let time_format = history::time_format();
+    for (number, entry) in entries {
+        if time_format.is_empty() {
+            println!("{:5}  {}", number, entry.command);
+        } else {
+            let time = format_time(entry.time, &time_format);
+            println!("{:5}  {} {}", number, time, entry.command);
+        }
+    }
+    exit_status(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        assert!(history(&[]).success());
+        assert!(history(&["5"]).success());
+        assert!(history(&["-p", "echo"]).success());
+        assert!(!history(&["-d", "100000"]).success());
+        assert_eq!(history(&["-x"]), exit_status(2));
+        assert_eq!(history(&["-d"]), exit_status(2));
+    }
+}
@@ -0,0 +1,196 @@
+use crate::jobs::{self, format_job, Jobs, State, JOBS};
+use crate::util::exit_status;
+use nix::unistd::Pid;
+use std::process::ExitStatus;
+
+/// Resolve the job specifications given to a builtin, the current job if
+/// there are none. Unknown jobs are reported and skipped.
+fn find_jobs(name: &str, jobs: &Jobs, specs: &[&str]) -> (Vec<usize>, bool) {
+    if specs.is_empty() {
+        return match jobs.current() {
+            Some(id) => (vec![id], true),
+            None => {
+                eprintln!("{}: current: no such job", name);
+                (vec![], false)
+            }
+        };
+    }
+
+    let mut ok = true;
+    let ids = specs
+        .iter()
+        .filter_map(|spec| match jobs.find(spec) {
+            Ok(id) => Some(id),
+            Err(x) => {
+                eprintln!("{}: {}", name, x);
+                ok = false;
+                None
+            }
+        })
+        .collect();
+    (ids, ok)
+}
+
+/// List the jobs of the shell. `-l` also shows their process group and `-p`
+/// shows nothing else. Finished jobs are forgotten once listed.
+pub fn jobs(args: &[&str]) -> ExitStatus {
+    let (mut long, mut pids) = (false, false);
+    let mut index = 0;
+    while let Some(flags) = args.get(index).and_then(|x| x.strip_prefix('-')) {
+        index += 1;
+        if flags == "-" {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                'l' => long = true,
+                'p' => pids = true,
+                x => {
+                    eprintln!("jobs: -{}: invalid option", x);
+                    eprintln!("jobs: usage: jobs [-lp] [jobspec ...]");
+                    return exit_status(2);
+                }
+            }
+        }
+    }
+
+    jobs::reap();
+    let mut jobs = JOBS.lock().unwrap();
+    let (ids, ok) = if args[index..].is_empty() {
+        (jobs.all().iter().map(|x| x.id).collect(), true)
+    } else {
+        find_jobs("jobs", &jobs, &args[index..])
+    };
+
+    for id in ids {
+        let job = match jobs.get(id) {
+            Some(x) => x,
+            None => continue,
+        };
+        if pids {
+            println!("{}", job.pgid);
+        } else {
+            println!("{}", format_job(&jobs, job, long));
+        }
+        if let State::Done(_) = job.state() {
+            jobs.remove(id);
+        }
+    }
+
+    exit_status(if ok { 0 } else { 1 })
+}
+
+/// Continue a job in the foreground and wait for it
+pub fn fg(args: &[&str]) -> ExitStatus {
+    if !jobs::enabled() {
+        eprintln!("fg: no job control");
+        return exit_status(1);
+    }
+    jobs::reap();
+
+    let (id, command) = {
+        let jobs = JOBS.lock().unwrap();
+        match find_jobs("fg", &jobs, &args[..args.len().min(1)]).0.first() {
+            Some(id) => (*id, jobs.get(*id).map(|x| x.command.clone())),
+            None => return exit_status(1),
+        }
+    };
+    println!("{}", command.unwrap_or_default());
+
+    if let Err(x) = jobs::resume(id) {
+        eprintln!("fg: {}", x);
+        return exit_status(1);
+    }
+    let codes = jobs::foreground(id);
+    exit_status(codes.last().copied().unwrap_or(0))
+}
+
+/// Continue stopped jobs in the background
+pub fn bg(args: &[&str]) -> ExitStatus {
+    if !jobs::enabled() {
+        eprintln!("bg: no job control");
+        return exit_status(1);
+    }
+    jobs::reap();
+
+    let (ids, mut ok) = find_jobs("bg", &JOBS.lock().unwrap(), args);
+    for id in ids {
+        if JOBS.lock().unwrap().get(id).map(|x| x.state()) == Some(State::Running) {
+            eprintln!("bg: job {} already in background", id);
+            continue;
+        }
+        if let Err(x) = jobs::resume(id) {
+            eprintln!("bg: {}", x);
+            ok = false;
+            continue;
+        }
+
+        let jobs = JOBS.lock().unwrap();
+        if let Some(job) = jobs.get(id) {
+            println!("[{}]{} {} &", id, jobs.marker(id), job.command);
+        }
+    }
+
+    exit_status(if ok { 0 } else { 1 })
+}
+
+/// Wait for the given jobs or processes, or for every job without arguments.
+/// The status is the one of the last job waited for.
+pub fn wait(args: &[&str]) -> ExitStatus {
+    if args.is_empty() {
+        let ids = JOBS
+            .lock()
+            .unwrap()
+            .all()
+            .iter()
+            .map(|x| x.id)
+            .collect::<Vec<_>>();
+        for id in ids {
+            jobs::wait(id);
+        }
+        return exit_status(0);
+    }
+
+    let mut status = 0;
+    for arg in args {
+        let id = {
+            let jobs = JOBS.lock().unwrap();
+            if arg.starts_with('%') {
+                jobs.find(arg)
+            } else {
+                match arg.parse::<i32>() {
+                    Ok(pid) => jobs
+                        .find_pid(Pid::from_raw(pid))
+                        .ok_or_else(|| format!("pid {} is not a child of this shell", pid)),
+                    Err(_) => Err(format!("`{}': not a pid or valid job spec", arg)),
+                }
+            }
+        };
+
+        status = match id {
+            Ok(id) => jobs::wait(id),
+            Err(x) => {
+                eprintln!("wait: {}", x);
+                127
+            }
+        };
+    }
+
+    exit_status(status)
+}
+
+/// Remove jobs from the table, the current one by default or all with `-a`.
+/// The shell no longer reports or waits for them.
+pub fn disown(args: &[&str]) -> ExitStatus {
+    let mut jobs = JOBS.lock().unwrap();
+    let (ids, ok) = match args {
+        ["-a"] => (jobs.all().iter().map(|x| x.id).collect(), true),
+        args => find_jobs("disown", &jobs, args),
+    };
+
+    for id in ids {
+        jobs.remove(id);
+    }
+
+    exit_status(if ok { 0 } else { 1 })
+}
@@ -0,0 +1,36 @@
+use crate::util::exit_status;
+use crate::variables::{split_assignment, VARIABLES};
+use std::process::ExitStatus;
+
+/// Declare variables visible only to the current function and its callees
+pub fn local(args: &[&str]) -> ExitStatus {
+    let mut variables = VARIABLES.lock().unwrap();
+    let mut status = 0;
+
+    for arg in args {
+        let (name, value) = split_assignment(arg);
+        let result = variables
+            .declare_local(name)
+            .and_then(|_| value.map_or(Ok(()), |x| variables.set(name, x)));
+        if let Err(x) = result {
+            eprintln!("local: {}", x);
+            status = 1;
+        }
+    }
+
+    exit_status(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_outside_function() {
+        assert!(!local(&["RUSH_TEST_LOCAL_BUILTIN=1"]).success());
+        assert_eq!(
+            VARIABLES.lock().unwrap().get("RUSH_TEST_LOCAL_BUILTIN"),
+            None
+        );
+    }
+}
@@ -1,6 +1,47 @@
+pub mod alias;
+pub mod arithmetic;
 pub mod basename;
 pub mod cd;
+pub mod colon;
+pub mod complete;
+pub mod declare;
 pub mod dirname;
 pub mod exec;
 pub mod exit;
+pub mod export;
+pub mod flow;
+pub mod history;
+pub mod jobs;
+pub mod local;
 pub mod pwd;
+pub mod readonly;
+pub mod set;
+pub mod shopt;
+pub mod source;
+pub mod trap;
+pub mod unset;
+
+use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+/// The status of a command that exited with `code`. Commands killed by a
+/// signal are reported with the code 128 plus the signal number.
+pub fn exit_status(code: i32) -> ExitStatus {
+    ExitStatusExt::from_raw((code & 0xff) << 8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executer::status_code;
+
+    #[test]
+    fn test_exit_status() {
+        assert!(exit_status(0).success());
+        assert!(!exit_status(1).success());
+        for code in &[0, 1, 2, 126, 127, 130, 255] {
+            assert_eq!(status_code(exit_status(*code)), *code);
+        }
+        assert_eq!(status_code(exit_status(256)), 0);
+        assert_eq!(status_code(ExitStatusExt::from_raw(11)), 139);
+    }
+}
@@ -1,19 +1,20 @@
-use std::{env, os::unix::process::ExitStatusExt, process::ExitStatus};
+use crate::util::exit_status;
+use std::{env, process::ExitStatus};
 
 /// Return directory portion of pathname
 pub fn pwd(args: &[&str]) -> ExitStatus {
     if !args.is_empty() {
         eprintln!("Too many arguments");
-        ExitStatusExt::from_raw(1)
+        exit_status(1)
     } else {
         match env::current_dir() {
             Ok(x) => {
                 println!("{}", x.into_os_string().to_str().unwrap());
-                ExitStatusExt::from_raw(0)
+                exit_status(0)
             }
             Err(x) => {
                 eprintln!("{}", x);
-                ExitStatusExt::from_raw(2)
+                exit_status(2)
             }
         }
     }
@@ -0,0 +1,52 @@
+use crate::util::exit_status;
+use crate::variables::{quote, split_assignment, VARIABLES};
+use std::process::ExitStatus;
+
+/// Mark variables as readonly, optionally assigning them a value first
+pub fn readonly(args: &[&str]) -> ExitStatus {
+    let operands = match args.first() {
+        Some(&"-p") | Some(&"--") => &args[1..],
+        _ => args,
+    };
+
+    let mut variables = VARIABLES.lock().unwrap();
+    if operands.is_empty() {
+        for name in variables.names() {
+            if variables.is_readonly(&name) {
+                let value = variables.get(&name).unwrap_or_default();
+                println!("readonly {}={}", name, quote(&value));
+            }
+        }
+        return exit_status(0);
+    }
+
+    let mut status = 0;
+    for operand in operands {
+        let (name, value) = split_assignment(operand);
+        let result = value
+            .map_or(Ok(()), |x| variables.set(name, x))
+            .and_then(|_| variables.make_readonly(name));
+        if let Err(x) = result {
+            eprintln!("readonly: {}", x);
+            status = 1;
+        }
+    }
+
+    exit_status(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readonly() {
+        assert!(readonly(&["RUSH_TEST_READONLY=1"]).success());
+        assert!(VARIABLES
+            .lock()
+            .unwrap()
+            .set("RUSH_TEST_READONLY", "2")
+            .is_err());
+        assert!(!readonly(&["RUSH_TEST_READONLY=3"]).success());
+    }
+}
@@ -0,0 +1,113 @@
+use crate::globals::POSITIONAL_PARAMETERS;
+use crate::options::{Options, OPTIONS, SET_OPTIONS};
+use crate::util::exit_status;
+use crate::variables::{quote, VARIABLES};
+use std::process::ExitStatus;
+
+const USAGE: &str = "set: usage: set [-Cefnux] [-o option-name] [--] [arg ...]";
+
+/// Turn shell options on with `-` and off with `+`, given as single letters
+/// or as `-o name`, and replace the positional parameters with the remaining
+/// arguments. Without arguments the variables are printed, and `-o` or `+o`
+/// without a name print the options.
+pub fn set(args: &[&str]) -> ExitStatus {
+    if args.is_empty() {
+        let variables = VARIABLES.lock().unwrap();
+        for name in variables.names() {
+            let value = variables.get(&name).unwrap_or_default();
+            println!("{}={}", name, quote(&value));
+        }
+        return exit_status(0);
+    }
+
+    let mut options = OPTIONS.lock().unwrap();
+    let mut parameters = None;
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        index += 1;
+        match *arg {
+            "--" => {
+                parameters = Some(&args[index..]);
+                break;
+            }
+            // A lone `-` ends the options without clearing the parameters
+            "-" => {
+                parameters = Some(&args[index..]).filter(|x| !x.is_empty());
+                break;
+            }
+            x if x.len() > 1 && (x.starts_with('-') || x.starts_with('+')) => {
+                let value = x.starts_with('-');
+                for flag in x[1..].chars() {
+                    let result = if flag == 'o' {
+                        match args.get(index) {
+                            Some(name) => {
+                                index += 1;
+                                set_option(&mut options, name, value)
+                            }
+                            None => {
+                                print_options(&mut options, value);
+                                Ok(())
+                            }
+                        }
+                    } else {
+                        options.set_flag(flag, value)
+                    };
+                    if let Err(x) = result {
+                        eprintln!("set: {}", x);
+                        eprintln!("{}", USAGE);
+                        return exit_status(2);
+                    }
+                }
+            }
+            _ => {
+                parameters = Some(&args[index - 1..]);
+                break;
+            }
+        }
+    }
+
+    if let Some(parameters) = parameters {
+        *POSITIONAL_PARAMETERS.lock().unwrap() = parameters.iter().map(|x| x.to_string()).collect();
+    }
+    exit_status(0)
+}
+
+fn set_option(options: &mut Options, name: &str, value: bool) -> Result<(), String> {
+    if SET_OPTIONS.iter().any(|(x, _)| *x == name) {
+        options.set(name, value)
+    } else {
+        Err(format!("{}: invalid option name", name))
+    }
+}
+
+/// Print the state of every option, as a table with `-o` or as the commands
+/// restoring it with `+o`
+fn print_options(options: &mut Options, table: bool) {
+    for (name, _) in SET_OPTIONS.iter() {
+        let enabled = options.get(name).unwrap_or_default();
+        if table {
+            println!("{:<15}\t{}", name, if enabled { "on" } else { "off" });
+        } else {
+            println!("set {}o {}", if enabled { '-' } else { '+' }, name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set() {
+        assert!(set(&["-o", "pipefail", "+C", "--", "a", "b"]).success());
+        assert!(OPTIONS.lock().unwrap().pipefail);
+        assert_eq!(*POSITIONAL_PARAMETERS.lock().unwrap(), vec!["a", "b"]);
+        assert!(set(&["+o", "pipefail", "c"]).success());
+        assert!(!OPTIONS.lock().unwrap().pipefail);
+        assert_eq!(*POSITIONAL_PARAMETERS.lock().unwrap(), vec!["c"]);
+        assert!(set(&["--"]).success());
+        assert!(POSITIONAL_PARAMETERS.lock().unwrap().is_empty());
+        assert!(!set(&["-o", "nothing"]).success());
+        assert!(!set(&["-z"]).success());
+    }
+}
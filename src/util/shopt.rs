@@ -0,0 +1,111 @@
+use crate::options::{OPTIONS, SET_OPTIONS, SHOPT_NAMES};
+use crate::util::exit_status;
+use std::process::ExitStatus;
+
+/// Enable (`-s`) or disable (`-u`) shell options, or print their state.
+/// With `-q` nothing is printed and the status tells if all are enabled, and
+/// with `-o` the names are the ones of `set -o`.
+pub fn shopt(args: &[&str]) -> ExitStatus {
+    let (mut set, mut unset, mut quiet, mut print) = (false, false, false, false);
+    let mut set_options = false;
+    let mut index = 0;
+    while let Some(flags) = args.get(index).and_then(|x| x.strip_prefix('-')) {
+        index += 1;
+        if flags == "-" {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                's' => set = true,
+                'u' => unset = true,
+                'q' => quiet = true,
+                'p' => print = true,
+                'o' => set_options = true,
+                x => {
+                    eprintln!("shopt: -{}: invalid option", x);
+                    eprintln!("shopt: usage: shopt [-pqsu] [-o] [optname ...]");
+                    return exit_status(2);
+                }
+            }
+        }
+    }
+    if set && unset {
+        eprintln!("shopt: cannot set and unset shell options simultaneously");
+        return exit_status(1);
+    }
+
+    let valid_names = if set_options {
+        SET_OPTIONS.iter().map(|(name, _)| *name).collect()
+    } else {
+        SHOPT_NAMES.to_vec()
+    };
+    let mut options = OPTIONS.lock().unwrap();
+    let names = &args[index..];
+    if names.is_empty() {
+        // Without names list every option, or only the ones in the given state
+        for name in valid_names {
+            let enabled = options.get(name).unwrap_or_default();
+            if !(set || unset) || enabled == set {
+                print_option(name, enabled, print, quiet, set_options);
+            }
+        }
+        return exit_status(0);
+    }
+
+    let mut status = 0;
+    for name in names {
+        let result = if !valid_names.contains(name) {
+            Err(format!("{}: invalid shell option name", name))
+        } else if set || unset {
+            options.set(name, set)
+        } else {
+            match options.get(name) {
+                Some(enabled) => {
+                    print_option(name, enabled, print, quiet, set_options);
+                    if !enabled {
+                        status = 1;
+                    }
+                    Ok(())
+                }
+                None => Err(format!("{}: invalid shell option name", name)),
+            }
+        };
+        if let Err(x) = result {
+            eprintln!("shopt: {}", x);
+            status = 1;
+        }
+    }
+
+    exit_status(status)
+}
+
+fn print_option(name: &str, enabled: bool, print: bool, quiet: bool, set_options: bool) {
+    if quiet {
+        return;
+    }
+
+    if print && set_options {
+        println!("set {}o {}", if enabled { '-' } else { '+' }, name);
+    } else if print {
+        println!("shopt {} {}", if enabled { "-s" } else { "-u" }, name);
+    } else {
+        println!("{:<15}\t{}", name, if enabled { "on" } else { "off" });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shopt() {
+        assert!(shopt(&["-s", "globstar"]).success());
+        assert!(shopt(&["-q", "globstar"]).success());
+        assert!(shopt(&["-u", "globstar"]).success());
+        assert!(!shopt(&["-q", "globstar"]).success());
+        assert!(!shopt(&["-s", "nothing"]).success());
+        assert!(!shopt(&["-s", "noglob"]).success());
+        assert!(shopt(&["-qu", "-o", "noglob"]).success());
+        assert!(!shopt(&["-x"]).success());
+    }
+}
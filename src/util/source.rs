@@ -0,0 +1,68 @@
+use crate::{executer, globals::POSITIONAL_PARAMETERS, util::exit_status};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+};
+
+/// Execute a file in the current shell. A name without `/` is looked up in
+/// `PATH`, then in the current directory. Further arguments replace the
+/// positional parameters while the file runs.
+pub fn source(args: &[&str]) -> ExitStatus {
+    let name = match args.first() {
+        Some(x) => x,
+        None => {
+            eprintln!(".: filename argument required");
+            eprintln!(".: usage: . filename [arguments]");
+            return exit_status(2);
+        }
+    };
+
+    let saved = if args.len() > 1 {
+        let parameters = args[1..].iter().map(|x| x.to_string()).collect();
+        Some(std::mem::replace(
+            &mut *POSITIONAL_PARAMETERS.lock().unwrap(),
+            parameters,
+        ))
+    } else {
+        None
+    };
+
+    let status = match executer::execute_file(&find(name)) {
+        Ok(status) => status,
+        Err(x) => {
+            eprintln!("rush: {}: {}", name, executer::error_text(&x));
+            exit_status(1)
+        }
+    };
+
+    if let Some(parameters) = saved {
+        *POSITIONAL_PARAMETERS.lock().unwrap() = parameters;
+    }
+    status
+}
+
+/// The path of a file to source
+fn find(name: &str) -> PathBuf {
+    if !name.contains('/') {
+        let path = env::var("PATH").unwrap_or_default();
+        for dir in path.split(':').filter(|x| !x.is_empty()) {
+            let candidate = Path::new(dir).join(name);
+            if candidate.is_file() {
+                return candidate;
+            }
+        }
+    }
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_errors() {
+        assert_eq!(source(&[]), exit_status(2));
+        assert_eq!(source(&["/nonexistent/file"]), exit_status(1));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::signals::{self, Trap};
+use crate::util::exit_status;
+use nix::sys::signal::Signal;
+use std::process::ExitStatus;
+
+/// Quote a trap command so that it can be read back by the shell
+fn quote(command: &str) -> String {
+    format!("'{}'", command.replace('\'', "'\\''"))
+}
+
+fn print_trap(trap: Trap, command: &str) {
+    println!("trap -- {} {}", quote(command), trap.name());
+}
+
+/// Set commands to run when the shell receives a signal, exits (`EXIT`),
+/// runs a simple command (`DEBUG`), a command fails (`ERR`) or a function
+/// returns (`RETURN`). An empty command ignores the condition and `-` resets
+/// it. Without a command the traps are listed.
+pub fn trap(args: &[&str]) -> ExitStatus {
+    let (mut print, mut list) = (false, false);
+    let mut index = 0;
+    while let Some(flags) = args.get(index).and_then(|x| x.strip_prefix('-')) {
+        if flags.is_empty() {
+            break;
+        }
+        index += 1;
+        if flags == "-" {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                'p' => print = true,
+                'l' => list = true,
+                x => {
+                    eprintln!("trap: -{}: invalid option", x);
+                    eprintln!("trap: usage: trap [-lp] [[arg] signal_spec ...]");
+                    return exit_status(2);
+                }
+            }
+        }
+    }
+    let args = &args[index..];
+
+    if list {
+        for signal in Signal::iterator() {
+            println!("{:2}) {}", signal as i32, signal.as_str());
+        }
+        return exit_status(0);
+    }
+
+    let mut status = 0;
+    let mut parse = |name: &str| {
+        let trap = Trap::parse(name);
+        if trap.is_none() {
+            eprintln!("trap: {}: invalid signal specification", name);
+            status = 1;
+        }
+        trap
+    };
+
+    if print || args.is_empty() {
+        if args.is_empty() {
+            for (trap, command) in signals::traps() {
+                print_trap(trap, &command);
+            }
+        }
+        for trap in args.iter().filter_map(|x| parse(x)) {
+            if let Some(command) = signals::trap(trap) {
+                print_trap(trap, &command);
+            }
+        }
+        return exit_status(status);
+    }
+
+    // A single operand or a first one that is a number are all conditions
+    let (command, names) = match args {
+        [_] => (None, args),
+        [first, ..] if first.parse::<u32>().is_ok() => (None, args),
+        [command, names @ ..] if *command == "-" => (None, names),
+        [command, names @ ..] => (Some(*command), names),
+        [] => unreachable!(),
+    };
+    for trap in names.iter().filter_map(|x| parse(x)) {
+        signals::set_trap(trap, command);
+    }
+
+    exit_status(status)
+}
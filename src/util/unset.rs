@@ -0,0 +1,54 @@
+use crate::{functions, util::exit_status, variables::VARIABLES};
+use std::process::ExitStatus;
+
+/// Remove variables or, with `-f`, functions
+pub fn unset(args: &[&str]) -> ExitStatus {
+    let mut functions_only = false;
+    let mut names = args;
+
+    while let Some(option) = names.first().filter(|x| x.starts_with('-')) {
+        match *option {
+            "-f" => functions_only = true,
+            "-v" => functions_only = false,
+            "--" => {
+                names = &names[1..];
+                break;
+            }
+            x => {
+                eprintln!("unset: {}: invalid option", x);
+                return exit_status(2);
+            }
+        }
+        names = &names[1..];
+    }
+
+    let mut status = 0;
+    for name in names {
+        if functions_only {
+            functions::remove(name);
+        } else if let Err(x) = VARIABLES.lock().unwrap().unset(name) {
+            eprintln!("unset: {}", x);
+            status = 1;
+        }
+    }
+
+    exit_status(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unset() {
+        VARIABLES
+            .lock()
+            .unwrap()
+            .set("RUSH_TEST_UNSET", "1")
+            .unwrap();
+        assert!(unset(&["RUSH_TEST_UNSET"]).success());
+        assert_eq!(VARIABLES.lock().unwrap().get("RUSH_TEST_UNSET"), None);
+        assert!(unset(&["-f", "rush_test_missing"]).success());
+        assert!(!unset(&["-q"]).success());
+    }
+}
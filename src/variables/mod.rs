@@ -0,0 +1,267 @@
+use lazy_static::lazy_static;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    sync::{Arc, Mutex},
+};
+
+/// Value and export state of a variable, saved to be restored later
+pub type Saved = Option<(String, bool)>;
+
+/// The shell variable store. Exported variables live in the process
+/// environment, which is inherited by spawned commands, while the other
+/// variables are only visible to the shell itself.
+#[derive(Debug, Default)]
+pub struct Variables {
+    shell: HashMap<String, String>,
+    readonly: HashSet<String>,
+    scopes: Vec<HashMap<String, Saved>>,
+}
+
+lazy_static! {
+    pub static ref VARIABLES: Arc<Mutex<Variables>> = Arc::new(Mutex::new(Variables::default()));
+}
+
+impl Variables {
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.shell
+            .get(name)
+            .cloned()
+            .or_else(|| env::var(name).ok())
+    }
+
+    pub fn is_exported(&self, name: &str) -> bool {
+        env::var_os(name).is_some()
+    }
+
+    pub fn is_readonly(&self, name: &str) -> bool {
+        self.readonly.contains(name)
+    }
+
+    /// Assign a variable, keeping its export state
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        if !is_valid_name(name) {
+            return Err(format!("`{}': not a valid identifier", name));
+        }
+        if self.is_readonly(name) {
+            return Err(format!("{}: readonly variable", name));
+        }
+
+        if self.is_exported(name) {
+            env::set_var(name, value);
+        } else {
+            self.shell.insert(name.to_string(), value.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn unset(&mut self, name: &str) -> Result<(), String> {
+        if self.is_readonly(name) {
+            return Err(format!("{}: cannot unset: readonly variable", name));
+        }
+
+        self.shell.remove(name);
+        env::remove_var(name);
+        Ok(())
+    }
+
+    /// Mark a variable to be passed to the environment of executed commands
+    pub fn export(&mut self, name: &str) -> Result<(), String> {
+        if !is_valid_name(name) {
+            return Err(format!("`{}': not a valid identifier", name));
+        }
+
+        let value = self.shell.remove(name).unwrap_or_default();
+        if !self.is_exported(name) {
+            env::set_var(name, value);
+        }
+        Ok(())
+    }
+
+    /// Stop passing a variable to executed commands while keeping its value
+    pub fn unexport(&mut self, name: &str) {
+        if let Ok(value) = env::var(name) {
+            env::remove_var(name);
+            self.shell.insert(name.to_string(), value);
+        }
+    }
+
+    pub fn make_readonly(&mut self, name: &str) -> Result<(), String> {
+        if !is_valid_name(name) {
+            return Err(format!("`{}': not a valid identifier", name));
+        }
+
+        self.readonly.insert(name.to_string());
+        Ok(())
+    }
+
+    /// Names of all set variables in alphabetical order
+    pub fn names(&self) -> Vec<String> {
+        let mut names = self
+            .shell
+            .keys()
+            .cloned()
+            .chain(env::vars_os().filter_map(|(x, _)| x.into_string().ok()))
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Number of function calls with a local variable scope
+    pub fn scope_depth(&self) -> usize {
+        self.scopes.len()
+    }
+
+    /// Start the local variable scope of a function call
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// End the innermost function call, restoring every variable it declared local
+    pub fn pop_scope(&mut self) {
+        if let Some(scope) = self.scopes.pop() {
+            for (name, saved) in scope {
+                self.restore(&name, saved);
+            }
+        }
+    }
+
+    pub fn save(&self, name: &str) -> Saved {
+        self.get(name).map(|x| (x, self.is_exported(name)))
+    }
+
+    /// Put back a variable saved with [`Variables::save`], even if it has
+    /// been made readonly since
+    pub fn restore(&mut self, name: &str, saved: Saved) {
+        self.shell.remove(name);
+        env::remove_var(name);
+        match saved {
+            Some((value, true)) => env::set_var(name, value),
+            Some((value, false)) => {
+                self.shell.insert(name.to_string(), value);
+            }
+            None => (),
+        }
+    }
+
+    /// Make a variable local to the innermost function call. Callers see the
+    /// variable with dynamic scoping until the function returns.
+    pub fn declare_local(&mut self, name: &str) -> Result<(), String> {
+        if !is_valid_name(name) {
+            return Err(format!("`{}': not a valid identifier", name));
+        }
+
+        let saved = self.save(name);
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.entry(name.to_string()).or_insert(saved);
+                Ok(())
+            }
+            None => Err("can only be used in a function".to_string()),
+        }
+    }
+}
+
+/// Return true if the name can be used for a variable or function
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(x) if x.is_ascii_alphabetic() || x == '_' => {
+            chars.all(|x| x.is_ascii_alphanumeric() || x == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Split a `name=value` argument into its name and optional value
+pub fn split_assignment(arg: &str) -> (&str, Option<&str>) {
+    match arg.find('=') {
+        Some(index) => (&arg[..index], Some(&arg[index + 1..])),
+        None => (arg, None),
+    }
+}
+
+/// Quote a value so that it is read back unchanged by the shell
+pub fn quote(value: &str) -> String {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || "_-./:=@%+,".contains(x))
+    {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_get() {
+        let mut variables = Variables::default();
+        variables.set("RUSH_TEST_SHELL_VAR", "1").unwrap();
+        assert_eq!(variables.get("RUSH_TEST_SHELL_VAR").unwrap(), "1");
+        assert!(!variables.is_exported("RUSH_TEST_SHELL_VAR"));
+        assert!(variables.set("1abc", "x").is_err());
+
+        variables.export("RUSH_TEST_SHELL_VAR").unwrap();
+        assert_eq!(env::var("RUSH_TEST_SHELL_VAR").unwrap(), "1");
+        variables.set("RUSH_TEST_SHELL_VAR", "2").unwrap();
+        assert_eq!(env::var("RUSH_TEST_SHELL_VAR").unwrap(), "2");
+
+        variables.unexport("RUSH_TEST_SHELL_VAR");
+        assert!(env::var("RUSH_TEST_SHELL_VAR").is_err());
+        assert_eq!(variables.get("RUSH_TEST_SHELL_VAR").unwrap(), "2");
+
+        variables.make_readonly("RUSH_TEST_SHELL_VAR").unwrap();
+        assert!(variables.set("RUSH_TEST_SHELL_VAR", "3").is_err());
+        assert!(variables.unset("RUSH_TEST_SHELL_VAR").is_err());
+    }
+
+    #[test]
+    fn test_local_scope() {
+        let mut variables = Variables::default();
+        variables.set("RUSH_TEST_LOCAL", "global").unwrap();
+        assert!(variables.declare_local("RUSH_TEST_LOCAL").is_err());
+
+        variables.push_scope();
+        variables.declare_local("RUSH_TEST_LOCAL").unwrap();
+        variables.declare_local("RUSH_TEST_LOCAL_NEW").unwrap();
+        variables.set("RUSH_TEST_LOCAL", "local").unwrap();
+        variables.set("RUSH_TEST_LOCAL_NEW", "local").unwrap();
+        assert_eq!(variables.get("RUSH_TEST_LOCAL").unwrap(), "local");
+        variables.pop_scope();
+
+        assert_eq!(variables.get("RUSH_TEST_LOCAL").unwrap(), "global");
+        assert_eq!(variables.get("RUSH_TEST_LOCAL_NEW"), None);
+    }
+
+    #[test]
+    fn test_save_restore() {
+        let mut variables = Variables::default();
+        variables.set("RUSH_TEST_SAVE", "1").unwrap();
+        let saved = variables.save("RUSH_TEST_SAVE");
+        let unset = variables.save("RUSH_TEST_SAVE_UNSET");
+
+        variables.set("RUSH_TEST_SAVE", "2").unwrap();
+        variables.export("RUSH_TEST_SAVE").unwrap();
+        variables.set("RUSH_TEST_SAVE_UNSET", "2").unwrap();
+        variables.restore("RUSH_TEST_SAVE", saved);
+        variables.restore("RUSH_TEST_SAVE_UNSET", unset);
+
+        assert_eq!(variables.get("RUSH_TEST_SAVE").unwrap(), "1");
+        assert!(!variables.is_exported("RUSH_TEST_SAVE"));
+        assert_eq!(variables.get("RUSH_TEST_SAVE_UNSET"), None);
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("abc"), "abc");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+    }
+}